@@ -1,10 +1,24 @@
 mod analyzer;
 mod compiler;
+#[cfg(feature = "serde")]
+mod dump;
 mod lexer;
 mod parser;
+mod printer;
+mod repl;
 mod types;
 mod vm;
 
-pub use compiler::compile;
+pub use analyzer::{analyze_root, Semantic};
+pub use compiler::{compile_root, compile_source, FuncSymbol, Program};
+#[cfg(feature = "serde")]
+pub use dump::{ast_json, program_json, semantic_json, to_json, tokens_json};
+pub use lexer::lex_source;
+pub use parser::parse_source;
+pub use printer::{format, Print};
+pub use repl::Repl;
 pub use types::*;
-pub use vm::VM;
+pub use vm::{
+    BufferOutput, FnInput, FnOutput, HeapStats, Input, Output, StdInput, StdOutput, StrInput,
+    Stream, VmBuilder, VM,
+};
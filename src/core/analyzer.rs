@@ -3,22 +3,36 @@ mod imp;
 
 use super::parser::{parse, Parser};
 use super::types::{ErrKind, RootNd, Type};
-use context::Context;
-pub use context::Semantic;
+pub use context::{Context, Semantic};
 
 pub trait Analyzer: Parser {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind>;
 }
 
+#[allow(dead_code)]
 pub fn analyze(code: &str) -> Result<(RootNd, Semantic), ErrKind> {
     let nd_res = parse::<RootNd>(code);
     if nd_res.is_err() {
         return Err(ErrKind::ParseErr);
     }
     let nd = nd_res.unwrap();
+    let info = analyze_root(&nd)?;
+    Ok((nd, info))
+}
+
+// the tree is only read, results go to the tables of Semantic
+pub fn analyze_root(nd: &RootNd) -> Result<Semantic, ErrKind> {
+    analyze_with_natives(nd, &[])
+}
+
+// the natives are declared first, index i of the slice is native i
+pub fn analyze_with_natives(nd: &RootNd, natives: &[(String, Type)]) -> Result<Semantic, ErrKind> {
     let mut cxt = Context::new();
+    for (i, (name, ty)) in natives.iter().enumerate() {
+        cxt.declare_native(name, ty, i)?;
+    }
     nd.analyze(&mut cxt)?;
-    Ok((nd, Semantic::new(cxt)?))
+    Semantic::new(cxt)
 }
 
 #[cfg(test)]
@@ -108,4 +122,153 @@ mod tests {
         let res = analyze(&code);
         assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
     }
+
+    #[test]
+    fn test_pointer() {
+        let code = load_code_from_file("test_cfiles/analyzer/ptr_0.c");
+        assert!(analyze(&code).is_ok());
+        // pointer level mismatch
+        let code = load_code_from_file("test_cfiles/analyzer/ptr_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // deref non pointer
+        let code = load_code_from_file("test_cfiles/analyzer/ptr_2.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_struct() {
+        // no such member
+        let code = load_code_from_file("test_cfiles/analyzer/struct_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("NoDeclare"), format!("{}", res.unwrap_err()));
+        // struct contains itself
+        let code = load_code_from_file("test_cfiles/analyzer/struct_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // an anonymous typedef struct leaves its name free as a tag
+        let code = load_code_from_file("test_cfiles/analyzer/struct_2.c");
+        assert!(analyze(&code).is_ok());
+    }
+
+    #[test]
+    fn test_enum() {
+        // assign to enumerator
+        let code = load_code_from_file("test_cfiles/analyzer/enum_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // enumerator value is not const
+        let code = load_code_from_file("test_cfiles/analyzer/enum_1.c-");
+        let res = analyze(&code);
+        assert_eq!(
+            String::from("GlobalNeedConst"),
+            format!("{}", res.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        // literal is a char array
+        let code = load_code_from_file("test_cfiles/analyzer/str_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // no room for the terminating zero
+        let code = load_code_from_file("test_cfiles/analyzer/str_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_const() {
+        // assign to const global
+        let code = load_code_from_file("test_cfiles/analyzer/const_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("AssignConst"), format!("{}", res.unwrap_err()));
+        // const dropped by a param
+        let code = load_code_from_file("test_cfiles/analyzer/const_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // const dropped by a struct pointer
+        let code = load_code_from_file("test_cfiles/analyzer/const_2.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // const pointer itself
+        let code = load_code_from_file("test_cfiles/analyzer/const_3.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("AssignConst"), format!("{}", res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_storage() {
+        // static local needs a constant initializer
+        let code = load_code_from_file("test_cfiles/analyzer/static_0.c-");
+        let res = analyze(&code);
+        assert_eq!(
+            String::from("GlobalNeedConst"),
+            format!("{}", res.unwrap_err())
+        );
+        // extern without definition
+        let code = load_code_from_file("test_cfiles/analyzer/extern_0.c-");
+        let res = analyze(&code);
+        assert_eq!(
+            String::from("ExternNoDefine"),
+            format!("{}", res.unwrap_err())
+        );
+        // definition differs from extern declaration
+        let code = load_code_from_file("test_cfiles/analyzer/extern_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_cast() {
+        // int to pointer needs a cast
+        let code = load_code_from_file("test_cfiles/analyzer/cast_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // struct is not castable
+        let code = load_code_from_file("test_cfiles/analyzer/cast_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_sizeof() {
+        // void has no size
+        let code = load_code_from_file("test_cfiles/analyzer/sizeof_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // array bound is not constant
+        let code = load_code_from_file("test_cfiles/analyzer/sizeof_1.c-");
+        let res = analyze(&code);
+        assert_eq!(
+            String::from("GlobalNeedConst"),
+            format!("{}", res.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn test_func_ptr() {
+        // params must match
+        let code = load_code_from_file("test_cfiles/analyzer/func_ptr_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // builtins have no address
+        let code = load_code_from_file("test_cfiles/analyzer/func_ptr_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_variadic() {
+        // va_start only in variadic funcs
+        let code = load_code_from_file("test_cfiles/analyzer/variadic_0.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+        // fixed params are still required
+        let code = load_code_from_file("test_cfiles/analyzer/variadic_1.c-");
+        let res = analyze(&code);
+        assert_eq!(String::from("TypeErr"), format!("{}", res.unwrap_err()));
+    }
 }
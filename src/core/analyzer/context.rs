@@ -1,15 +1,28 @@
-use crate::core::types::{get_type_size, ErrKind, FuncInfo, Layout, ScopeInfo, Type, VarInfo};
-use std::collections::HashMap;
+use crate::core::types::{
+    get_type_size, DeclareNd, ErrKind, ExprNd, FieldInfo, FuncInfo, Layout, NodeId, NodeTable,
+    ScopeInfo, StorageClass, StructInfo, Type, VarInfo, WarnKind, BUILTINS,
+};
+use std::collections::{HashMap, HashSet};
 
+#[derive(Clone)]
 pub struct Context {
     names: HashMap<String, Vec<(u32, u32)>>, // (scope_id, id)
     mem_layout: Vec<Layout>,
     vars: HashMap<u32, VarInfo>,
     scopes: HashMap<u32, ScopeInfo>,
     funcs: HashMap<u32, FuncInfo>,
+    structs: HashMap<String, StructInfo>,
+    consts: HashMap<u32, i32>,
+    enums: HashSet<String>,
     scope_stack: Vec<u32>,
+    warnings: Vec<WarnKind>,
+    table: NodeTable,
+    root: Option<NodeId>,
     cur_offset: usize,
+    static_offset: usize, // static locals, placed after the globals
     cur_func_id: u32,
+    committed: usize, // layouts below are final, see commit
+    redefine: bool,   // implemented funcs may be implemented again
 }
 
 impl Context {
@@ -20,20 +33,36 @@ impl Context {
             vars: HashMap::new(),
             scopes: HashMap::new(),
             funcs: HashMap::new(),
+            structs: HashMap::new(),
+            consts: HashMap::new(),
+            enums: HashSet::new(),
             scope_stack: Vec::new(),
+            warnings: Vec::new(),
+            table: NodeTable::new(),
+            root: None,
             cur_offset: 0,
+            static_offset: 0,
             cur_func_id: 0,
+            committed: 0,
+            redefine: false,
         };
         let id = cxt.new_mem_layout(); // into program
+        cxt.cur_offset = 1; // keep address 0 as null
         cxt.scope_stack.push(id);
         cxt.scopes.insert(
             id,
             ScopeInfo {
-                id: id,
+                id,
                 is_loop: false,
                 func_id: 0,
             },
         );
+        for b in BUILTINS.iter() {
+            let id = cxt.declare_fn(b.name(), &b.func_ty()).unwrap();
+            let finfo = cxt.funcs.get_mut(&id).unwrap();
+            finfo.has_impl = true;
+            finfo.builtin = Some(*b);
+        }
         cxt
     }
 
@@ -61,8 +90,8 @@ impl Context {
         self.scopes.insert(
             id,
             ScopeInfo {
-                id: id,
-                is_loop: is_loop,
+                id,
+                is_loop,
                 func_id: self.cur_func_id,
             },
         );
@@ -78,9 +107,18 @@ impl Context {
         self._enter_scope(true)
     }
 
+    pub fn enter_func(&mut self, id: u32) -> u32 {
+        self.cur_func_id = id;
+        // the frame starts here, globals may follow a prototype
+        self.mem_layout[id as usize] = Layout::new(self.cur_offset);
+        self.enter_scope()
+    }
+
     pub fn exit_scope(&mut self) {
         let idx = self.scope_stack.pop().unwrap() as usize;
         self.mem_layout[idx].end(self.cur_offset);
+        // vars of the scope are popped, later ones reuse the slots
+        self.cur_offset = self.mem_layout[idx].offset;
         if self.get_current_scope_id() == 0 {
             self.cur_func_id = 0;
         }
@@ -114,11 +152,82 @@ impl Context {
             Some(f) => Ok(f.ty.clone()),
             None => match self.vars.get(&id) {
                 Some(v) => Ok(v.ty.clone()),
+                None if self.consts.contains_key(&id) => Ok(Type::Int),
                 None => Err(ErrKind::NoDeclare),
             },
         }
     }
 
+    pub fn warn(&mut self, w: WarnKind) {
+        self.warnings.push(w);
+    }
+
+    pub fn warnings(&self) -> &[WarnKind] {
+        &self.warnings
+    }
+
+    pub fn is_in_memory(&self, id: u32) -> bool {
+        self.vars[&id].in_memory()
+    }
+
+    pub fn is_var(&self, id: u32) -> bool {
+        self.vars.contains_key(&id)
+    }
+
+    // builtins and natives, neither has code in the program
+    pub fn is_builtin(&self, id: u32) -> bool {
+        self.funcs
+            .get(&id)
+            .map_or(false, |f| f.builtin.is_some() || f.native.is_some())
+    }
+
+    // `f` or `&f` of a func with code, its address is set once linked
+    pub fn is_func_designator(&self, ex: &ExprNd) -> bool {
+        ex.try_to_designator().map_or(false, |v| {
+            let id = self.table.symbol(v.id);
+            self.funcs.contains_key(&id) && !self.is_builtin(id)
+        })
+    }
+
+    // value of a const global, known at compile time
+    pub fn bind_const(&mut self, id: u32, v: i32) {
+        self.consts.insert(id, v);
+    }
+
+    pub fn table(&self) -> &NodeTable {
+        &self.table
+    }
+
+    pub fn table_mut(&mut self) -> &mut NodeTable {
+        &mut self.table
+    }
+
+    pub fn set_root(&mut self, id: NodeId) {
+        self.root = Some(id);
+    }
+
+    pub fn retrieve_const(&self, ex: &ExprNd) -> Option<i32> {
+        ex.try_retrieve_const(&self.consts, &self.table)
+    }
+
+    pub fn retrieve_init_const(&self, nd: &DeclareNd) -> Option<i32> {
+        nd.try_retrieve_const(&self.consts, &self.table)
+    }
+
+    pub fn declare_enum(&mut self, name: &str) -> Result<(), ErrKind> {
+        if self.enums.insert(name.to_owned()) {
+            Ok(())
+        } else {
+            Err(ErrKind::ReDeclare)
+        }
+    }
+
+    pub fn declare_const(&mut self, name: &str, v: i32) -> Result<u32, ErrKind> {
+        let id = self.declare(name, 0)?;
+        self.consts.insert(id, v);
+        Ok(id)
+    }
+
     pub fn get_off_by_id(&self, id: u32) -> usize {
         self.cur_offset - self.mem_layout[id as usize].offset
     }
@@ -143,32 +252,190 @@ impl Context {
         }
     }
 
-    pub fn declare_var(&mut self, name: &str, ty: &Type) -> Result<u32, ErrKind> {
-        let id = self.declare(name, get_type_size(ty.clone()))?;
+    pub fn get_type_size(&self, ty: &Type) -> usize {
+        get_type_size(ty.clone(), &self.structs)
+    }
+
+    pub fn get_struct(&self, name: &str) -> Result<&StructInfo, ErrKind> {
+        self.structs.get(name).ok_or(ErrKind::NoDeclare)
+    }
+
+    pub fn declare_struct(&mut self, name: &str, fields: &[(Type, String)]) -> Result<(), ErrKind> {
+        if self.structs.contains_key(name) {
+            return Err(ErrKind::ReDeclare);
+        }
+        let mut infos: Vec<FieldInfo> = Vec::new();
+        let mut off = 0;
+        for (ty, fname) in fields.iter() {
+            if infos.iter().any(|f| f.name == *fname) {
+                return Err(ErrKind::ReDeclare);
+            }
+            let sz = self.get_type_size(ty);
+            if sz == 0 {
+                return Err(ErrKind::TypeErr);
+            }
+            let mut layout = Layout::new(off);
+            off += sz;
+            layout.end(off);
+            infos.push(FieldInfo {
+                name: fname.clone(),
+                ty: ty.clone(),
+                layout,
+            });
+        }
+        self.structs.insert(
+            name.to_owned(),
+            StructInfo {
+                name: name.to_owned(),
+                fields: infos,
+                size: off,
+            },
+        );
+        Ok(())
+    }
+
+    fn get_global_id(&self, name: &str) -> Option<u32> {
+        let ids = self.names.get(name)?;
+        ids.iter().find(|(sid, _)| *sid == 0).map(|(_, id)| *id)
+    }
+
+    pub fn declare_var(
+        &mut self,
+        name: &str,
+        ty: &Type,
+        storage: StorageClass,
+    ) -> Result<u32, ErrKind> {
+        let sz = self.get_type_size(ty);
+        if sz == 0 {
+            return Err(ErrKind::TypeErr);
+        }
+        if storage == StorageClass::Extern {
+            return self.declare_extern(name, ty);
+        }
+        let scope_id = self.get_current_scope_id();
+        if scope_id == 0 {
+            if let Some(id) = self.get_global_id(name) {
+                return self.define_extern(id, ty, storage);
+            }
+        }
+        let id = if storage == StorageClass::Static && self.cur_func_id != 0 {
+            let id = self.declare(name, 0)?;
+            // offset inside the static region, moved behind the globals by Semantic
+            let mut layout = Layout::new(self.static_offset);
+            self.static_offset += sz;
+            layout.end(self.static_offset);
+            self.mem_layout[id as usize] = layout;
+            id
+        } else {
+            self.declare(name, sz)?
+        };
         self.vars.insert(
             id,
             VarInfo {
-                id: id,
-                scope_id: self.get_current_scope_id(),
+                id,
+                scope_id,
                 func_id: self.cur_func_id,
                 ty: ty.clone(),
+                storage,
             },
         );
         Ok(id)
     }
 
+    // refers to a global, which may be defined later in the file
+    fn declare_extern(&mut self, name: &str, ty: &Type) -> Result<u32, ErrKind> {
+        let id = match self.get_global_id(name) {
+            Some(id) => match self.vars.get(&id) {
+                Some(v) if v.ty == *ty => id,
+                _ => return Err(ErrKind::TypeErr),
+            },
+            None => {
+                let id = self.new_mem_layout();
+                self.names.entry(name.to_owned()).or_default().push((0, id));
+                self.vars.insert(
+                    id,
+                    VarInfo {
+                        id,
+                        scope_id: 0,
+                        func_id: 0,
+                        ty: ty.clone(),
+                        storage: StorageClass::Extern,
+                    },
+                );
+                id
+            }
+        };
+        let scope_id = self.get_current_scope_id();
+        if scope_id != 0 {
+            let ids = self.names.get_mut(name).unwrap();
+            if ids.iter().any(|(sid, _)| *sid == scope_id) {
+                return Err(ErrKind::ReDeclare);
+            }
+            ids.push((scope_id, id));
+        }
+        Ok(id)
+    }
+
+    // file scope definition of a name declared extern before
+    fn define_extern(&mut self, id: u32, ty: &Type, storage: StorageClass) -> Result<u32, ErrKind> {
+        let info = match self.vars.get_mut(&id) {
+            Some(v) if v.storage == StorageClass::Extern => v,
+            _ => return Err(ErrKind::ReDeclare),
+        };
+        if info.ty != *ty {
+            return Err(ErrKind::TypeErr);
+        }
+        info.storage = storage;
+        let mut layout = Layout::new(self.cur_offset);
+        self.cur_offset += get_type_size(ty.clone(), &self.structs);
+        layout.end(self.cur_offset);
+        self.mem_layout[id as usize] = layout;
+        Ok(id)
+    }
+
     pub fn declare_fn(&mut self, name: &str, ty: &Type) -> Result<u32, ErrKind> {
         let id = self.declare(name, 0)?;
-        self.cur_func_id = id;
         self.funcs.insert(id, FuncInfo::new(id, ty.clone()));
         Ok(id)
     }
 
+    // a prototype of a native only restates it
+    pub fn declare_proto(&mut self, name: &str, ty: &Type) -> Result<u32, ErrKind> {
+        let native = self
+            .fetch(name)
+            .ok()
+            .filter(|id| self.funcs.get(id).map_or(false, |f| f.native.is_some()));
+        match native {
+            Some(id) if self.funcs[&id].ty == *ty => Ok(id),
+            Some(_) => Err(ErrKind::TypeErr),
+            None => self.declare_fn(name, ty),
+        }
+    }
+
+    // A function of the host, called through the natives of the vm at
+    // `idx`. Params and the result are single cells
+    pub fn declare_native(&mut self, name: &str, ty: &Type, idx: usize) -> Result<u32, ErrKind> {
+        let (ret, params) = match ty {
+            Type::Func(v, false) => v.split_last().unwrap(),
+            _ => return Err(ErrKind::TypeErr),
+        };
+        let scalar = |t: &Type| t.unqualified().is_scalar();
+        if !params.iter().all(scalar) || !(scalar(ret) || *ret == Type::Void) {
+            return Err(ErrKind::TypeErr);
+        }
+        let id = self.declare_fn(name, ty)?;
+        let finfo = self.funcs.get_mut(&id).unwrap();
+        finfo.has_impl = true;
+        finfo.native = Some(idx);
+        Ok(id)
+    }
+
     pub fn impl_fn(&mut self, name: &str, ty: &Type) -> Result<u32, ErrKind> {
         match self.fetch(name) {
             Ok(id) => {
                 let finfo = self.funcs.get_mut(&id).unwrap();
-                if finfo.has_impl {
+                let external = finfo.builtin.is_some() || finfo.native.is_some();
+                if finfo.has_impl && (!self.redefine || external) {
                     Err(ErrKind::ReImpl)
                 } else if finfo.ty != ty.clone() {
                     Err(ErrKind::TypeErr)
@@ -186,53 +453,112 @@ impl Context {
     }
 }
 
-#[derive(Debug)]
+impl Context {
+    // for a repl, where a new impl replaces the old one
+    pub fn allow_redefine(&mut self) {
+        self.redefine = true;
+    }
+
+    // next globals go at `end` or later, the cells before are taken
+    pub fn skip_to(&mut self, end: usize) {
+        self.cur_offset = self.cur_offset.max(end);
+    }
+
+    pub fn check_defined(&self) -> Result<(), ErrKind> {
+        if self.funcs.values().any(|f| !f.has_impl) {
+            return Err(ErrKind::FuncNoImpl);
+        }
+        if self
+            .vars
+            .values()
+            .any(|v| v.storage == StorageClass::Extern)
+        {
+            return Err(ErrKind::ExternNoDefine);
+        }
+        Ok(())
+    }
+
+    // Fix the layouts analyzed since the last commit: static locals go
+    // behind the globals, locals and scopes become frame offsets
+    pub fn commit(&mut self) {
+        let from = self.committed as u32;
+        for v in self.vars.values().filter(|v| v.id >= from) {
+            if !v.is_global() && v.in_memory() {
+                self.mem_layout[v.id as usize].offset += self.cur_offset;
+            } else if !v.is_global() {
+                let off = self.mem_layout[v.func_id as usize].offset;
+                self.mem_layout[v.id as usize].offset -= off;
+            }
+        }
+        for v in self.scopes.values().filter(|v| v.id >= from) {
+            if !v.is_global() {
+                let off = self.mem_layout[v.func_id as usize].offset;
+                self.mem_layout[v.id as usize].offset -= off;
+            }
+        }
+        self.cur_offset += self.static_offset;
+        self.static_offset = 0;
+        self.mem_layout[0].end(self.cur_offset);
+        self.committed = self.mem_layout.len();
+    }
+
+    // analysis so far, `entry` is where the program starts
+    pub fn snapshot(&mut self, entry: u32) -> Semantic {
+        self.commit();
+        Semantic {
+            mem_layout: self.mem_layout.clone(),
+            vars: self.vars.clone(),
+            funcs: self.funcs.clone(),
+            scopes: self.scopes.clone(),
+            structs: self.structs.clone(),
+            consts: self.consts.clone(),
+            warnings: self.warnings.clone(),
+            table: self.table.clone(),
+            root: self.root,
+            main_func_id: entry,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Semantic {
     pub mem_layout: Vec<Layout>,
     pub vars: HashMap<u32, VarInfo>,
     pub funcs: HashMap<u32, FuncInfo>,
     pub scopes: HashMap<u32, ScopeInfo>,
+    pub structs: HashMap<String, StructInfo>,
+    pub consts: HashMap<u32, i32>,
+    pub warnings: Vec<WarnKind>,
+    pub table: NodeTable,
+    pub root: Option<NodeId>, // the tree analyzed last
     pub main_func_id: u32,
 }
 
 impl Semantic {
-    pub fn new(cxt: Context) -> Result<Self, ErrKind> {
+    pub fn new(mut cxt: Context) -> Result<Self, ErrKind> {
         let main_id = match cxt.fetch("main") {
             Ok(id) => id,
             Err(_) => return Err(ErrKind::NoMainFunc),
         };
         let ty = cxt.get_type_by_id(main_id)?;
-        if ty != Type::Func(vec![Type::Int]) {
+        if ty != Type::Func(vec![Type::Int], false) {
             // main type error
             return Err(ErrKind::TypeErr);
         }
-        for (_, info) in cxt.funcs.iter() {
-            if !info.has_impl {
-                // no impl func
-                return Err(ErrKind::FuncNoImpl);
-            }
-        }
-        let mut mem_layout = cxt.mem_layout;
-        for (_, v) in cxt.vars.iter() {
-            if !v.is_global() {
-                let off = mem_layout[v.func_id as usize].offset;
-                mem_layout[v.id as usize].offset -= off;
-            }
-        }
-        for (_, v) in cxt.scopes.iter() {
-            if !v.is_global() {
-                let off = mem_layout[v.func_id as usize].offset;
-                mem_layout[v.id as usize].offset -= off;
-            }
-        }
-        // for l in mem_layout.iter() {
-        //     println!("layout: {:?}", l.clone());
-        // }
+        cxt.check_defined()?;
+        cxt.commit();
         Ok(Semantic {
-            mem_layout: mem_layout,
+            mem_layout: cxt.mem_layout,
             vars: cxt.vars,
             funcs: cxt.funcs,
             scopes: cxt.scopes,
+            structs: cxt.structs,
+            consts: cxt.consts,
+            warnings: cxt.warnings,
+            table: cxt.table,
+            root: cxt.root,
             main_func_id: main_id,
         })
     }
@@ -2,74 +2,470 @@ use super::context::Context;
 use super::Analyzer;
 use crate::core::types::nodes::*;
 use crate::core::types::{
-    get_op_param_num, get_type_size, get_value_type, CalcItem, ErrKind, Type,
+    calc_trunc, get_op_param_num, get_value_type, CalcInfo, CalcItem, ErrKind, Op, StorageClass,
+    Type, Value, WarnKind,
 };
 
+struct Operand {
+    idx: usize,
+    ty: Type,
+    lvalue: bool,
+}
+
+// use an operand as value: load lvalues and decay arrays
+fn into_value(cxt: &Context, infos: &mut [CalcInfo], o: Operand) -> Result<Type, ErrKind> {
+    // values are never const
+    let ty = match o.ty.unqualified().clone() {
+        Type::Array(t, _) if o.lvalue => Type::Ptr(t),
+        Type::Array(_, _) => return Err(ErrKind::TypeErr),
+        // functions decay to pointers, their address is the value
+        t @ Type::Func(_, _) => Type::Ptr(Box::new(t)),
+        t => {
+            if o.lvalue {
+                infos[o.idx].load = cxt.get_type_size(&t);
+            }
+            t
+        }
+    };
+    infos[o.idx].ty = ty.clone();
+    Ok(ty)
+}
+
+fn is_null_operand(stack: &[CalcItem], o: &Operand) -> bool {
+    matches!(
+        stack[o.idx],
+        CalcItem::Factor(FactorNd::Value(Value::Int(0)))
+    )
+}
+
+// pointees differing only in const
+fn same_pointee(t: &Type, t2: &Type) -> bool {
+    t.unqualified() == t2.unqualified()
+}
+
+fn pointee_size(cxt: &Context, t: &Type) -> Result<usize, ErrKind> {
+    match cxt.get_type_size(t) {
+        0 => Err(ErrKind::TypeErr),
+        n => Ok(n),
+    }
+}
+
+// how the result of an expression is used
+#[derive(PartialEq)]
+enum Usage {
+    Value,
+    Lvalue,
+    Sizeof, // type as is, arrays are not decayed
+}
+
+fn analyze_calc(ex: &ExprNd, cxt: &mut Context, usage: Usage) -> Result<Type, ErrKind> {
+    let mut infos = Vec::new();
+    let mut st: Vec<Operand> = Vec::new();
+    for (idx, it) in ex.stack.iter().enumerate() {
+        match it {
+            CalcItem::Op(op) => {
+                infos.push(CalcInfo::new(Type::Int));
+                let num = get_op_param_num(*op);
+                if st.len() < num {
+                    return Err(ErrKind::TypeErr);
+                }
+                let b = st.pop().unwrap();
+                let a = if num == 2 { st.pop() } else { None };
+                let (ty, lvalue) = match (op, a) {
+                    (Op::Deref, None) => match into_value(cxt, &mut infos, b)? {
+                        Type::Ptr(t) if *t.unqualified() != Type::Void => (*t, true),
+                        _ => return Err(ErrKind::TypeErr),
+                    },
+                    (Op::AddrOf, None) => {
+                        if !b.lvalue && !matches!(b.ty, Type::Func(_, _)) {
+                            return Err(ErrKind::TypeErr);
+                        }
+                        (Type::Ptr(Box::new(b.ty)), false)
+                    }
+                    (Op::Not, None) => {
+                        if !into_value(cxt, &mut infos, b)?.is_scalar() {
+                            return Err(ErrKind::TypeErr);
+                        }
+                        (Type::Int, false)
+                    }
+                    (_, None) => {
+                        if !into_value(cxt, &mut infos, b)?.is_integer() {
+                            return Err(ErrKind::TypeErr);
+                        }
+                        (Type::Int, false)
+                    }
+                    (_, Some(a)) => {
+                        let b_null = is_null_operand(&ex.stack, &b);
+                        let a_null = is_null_operand(&ex.stack, &a);
+                        let (a_idx, b_idx) = (a.idx, b.idx);
+                        let aty = into_value(cxt, &mut infos, a)?;
+                        let bty = into_value(cxt, &mut infos, b)?;
+                        match (op, aty, bty) {
+                            (Op::Index, Type::Ptr(t), i) if i.is_integer() => {
+                                infos[b_idx].scale = pointee_size(cxt, &t)?;
+                                (*t, true)
+                            }
+                            (Op::Index, _, _) => return Err(ErrKind::TypeErr),
+                            (_, aty, bty) if aty.is_integer() && bty.is_integer() => {
+                                (Type::Int, false)
+                            }
+                            (Op::Add | Op::Minus, Type::Ptr(t), i) if i.is_integer() => {
+                                infos[b_idx].scale = pointee_size(cxt, &t)?;
+                                (Type::Ptr(t), false)
+                            }
+                            (Op::Add, i, Type::Ptr(t)) if i.is_integer() => {
+                                infos[a_idx].scale = pointee_size(cxt, &t)?;
+                                (Type::Ptr(t), false)
+                            }
+                            (Op::Minus, Type::Ptr(t), Type::Ptr(t2)) if same_pointee(&t, &t2) => {
+                                infos[idx].div = pointee_size(cxt, &t)?;
+                                (Type::Int, false)
+                            }
+                            (Op::And | Op::Or, aty, bty) if aty.is_scalar() && bty.is_scalar() => {
+                                (Type::Int, false)
+                            }
+                            (
+                                Op::Eq
+                                | Op::NotEq
+                                | Op::GreaterEq
+                                | Op::GreaterThan
+                                | Op::LessEq
+                                | Op::LessThan,
+                                aty,
+                                bty,
+                            ) => match (aty, bty) {
+                                (Type::Ptr(t), Type::Ptr(t2)) if same_pointee(&t, &t2) => {
+                                    (Type::Int, false)
+                                }
+                                (Type::Ptr(_), Type::Int) if b_null => (Type::Int, false),
+                                (Type::Int, Type::Ptr(_)) if a_null => (Type::Int, false),
+                                _ => return Err(ErrKind::TypeErr),
+                            },
+                            _ => return Err(ErrKind::TypeErr),
+                        }
+                    }
+                };
+                infos[idx].ty = ty.clone();
+                st.push(Operand { idx, ty, lvalue });
+            }
+            CalcItem::Member(name) => {
+                infos.push(CalcInfo::new(Type::Int));
+                let o = match st.pop() {
+                    Some(o) => o,
+                    None => return Err(ErrKind::TypeErr),
+                };
+                let field = match o.ty.unqualified() {
+                    Type::Struct(sname) if o.lvalue => {
+                        match cxt.get_struct(sname)?.get_field(name) {
+                            Some(f) => f.clone(),
+                            None => return Err(ErrKind::NoDeclare),
+                        }
+                    }
+                    _ => return Err(ErrKind::TypeErr),
+                };
+                // members of a const struct are const
+                let ty = if o.ty.is_const() {
+                    field.ty.into_const()
+                } else {
+                    field.ty
+                };
+                infos[idx].ty = ty.clone();
+                infos[idx].off = field.layout.offset;
+                st.push(Operand {
+                    idx,
+                    ty,
+                    lvalue: true,
+                });
+            }
+            CalcItem::Cast(to) => {
+                infos.push(CalcInfo::new(Type::Int));
+                let o = match st.pop() {
+                    Some(o) => o,
+                    None => return Err(ErrKind::TypeErr),
+                };
+                let from = into_value(cxt, &mut infos, o)?;
+                let to = to.unqualified().clone();
+                if to == Type::Void {
+                    infos[idx].drop = cxt.get_type_size(&from);
+                } else if !to.is_scalar() || !from.is_scalar() {
+                    return Err(ErrKind::TypeErr);
+                }
+                infos[idx].trunc = trunc_bits(&to, &from);
+                infos[idx].ty = to.clone();
+                st.push(Operand {
+                    idx,
+                    ty: to,
+                    lvalue: false,
+                });
+            }
+            CalcItem::Factor(f) => {
+                let ty = f.analyze(cxt)?;
+                let lvalue = match f {
+                    // builtins have no code to point to
+                    FactorNd::Var(v) if cxt.is_builtin(cxt.table().symbol(v.id)) => {
+                        return Err(ErrKind::TypeErr);
+                    }
+                    FactorNd::Var(v) => cxt.is_var(cxt.table().symbol(v.id)),
+                    FactorNd::Str(_) => true,
+                    _ => false,
+                };
+                infos.push(CalcInfo::new(ty.clone()));
+                st.push(Operand { idx, ty, lvalue });
+            }
+        }
+    }
+    if st.len() != 1 {
+        return Err(ErrKind::TypeErr);
+    }
+    let top = st.pop().unwrap();
+    let ty = match usage {
+        Usage::Lvalue if !top.lvalue => return Err(ErrKind::TypeErr),
+        Usage::Lvalue | Usage::Sizeof => top.ty,
+        Usage::Value => into_value(cxt, &mut infos, top)?,
+    };
+    cxt.table_mut().set_infos(ex.id, infos);
+    Ok(ty)
+}
+
+// value of type `from` can be stored into `to`, pointers may gain const on the pointee
+// and convert to or from void *
+fn assignable(to: &Type, from: &Type, ex: &ExprNd) -> bool {
+    let to = to.unqualified();
+    let ptr_ok = match (to, from) {
+        (Type::Ptr(t), Type::Ptr(f)) => {
+            let is_func = matches!(**t, Type::Func(_, _)) || matches!(**f, Type::Func(_, _));
+            let void =
+                !is_func && (*t.unqualified() == Type::Void || *f.unqualified() == Type::Void);
+            (void || same_pointee(t, f)) && (t.is_const() || !f.is_const())
+        }
+        _ => false,
+    };
+    (to.is_scalar() || matches!(to, Type::Struct(_)))
+        && (to == from
+            || ptr_ok
+            || to.is_integer() && from.is_integer()
+            || to.is_ptr() && ex.is_null_const())
+}
+
+// bits kept when a `from` value becomes a `to`, 0 when nothing is lost
+fn trunc_bits(to: &Type, from: &Type) -> usize {
+    match (to, from) {
+        (Type::Char, Type::Char) => 0,
+        (Type::Char, _) => 8,
+        _ => 0,
+    }
+}
+
+// implicit conversion at assign, init, call and return
+fn convert(cxt: &mut Context, to: &Type, from: &Type, ex: &ExprNd) -> Result<(), ErrKind> {
+    if !assignable(to, from, ex) {
+        return Err(ErrKind::TypeErr);
+    }
+    let bits = trunc_bits(to.unqualified(), from);
+    if bits > 0 {
+        // constants that fit are fine
+        match cxt.retrieve_const(ex) {
+            Some(v) if calc_trunc(v, bits) == v => {}
+            _ => cxt.warn(WarnKind::Narrowing),
+        }
+        cxt.table_mut().set_trunc(ex.id, bits);
+    }
+    Ok(())
+}
+
+fn has_const_member(cxt: &Context, ty: &Type) -> bool {
+    match ty {
+        Type::Struct(name) => match cxt.get_struct(name) {
+            Ok(info) => info
+                .fields
+                .iter()
+                .any(|f| f.ty.is_const() || has_const_member(cxt, f.ty.unqualified())),
+            Err(_) => false,
+        },
+        Type::Array(t, _) => t.is_const() || has_const_member(cxt, t.unqualified()),
+        _ => false,
+    }
+}
+
 impl Analyzer for FactorNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         match self {
             FactorNd::Var(n) => n.analyze(cxt),
-            FactorNd::Value(n) => Ok(get_value_type(n.clone())),
+            FactorNd::Value(n) => Ok(get_value_type(*n)),
+            // literals live in read-only memory as char arrays
+            FactorNd::Str(s) => Ok(Type::Array(Box::new(Type::Char), s.chars().count() + 1)),
             FactorNd::Func(n) => n.analyze(cxt),
+            FactorNd::Indirect(n) => analyze_indirect(n, cxt),
+            FactorNd::Sizeof(n) => analyze_sizeof(n, cxt),
+            FactorNd::Va(n) => n.analyze(cxt),
         }
     }
 }
 
-impl Analyzer for ExprNd {
+// parsed inside the calc stack, so not a Parser node
+fn analyze_sizeof(n: &SizeofNd, cxt: &mut Context) -> Result<Type, ErrKind> {
+    let ty = match &n.arg {
+        SizeofArg::Type(t) => t.clone(),
+        SizeofArg::Expr(ex) => analyze_calc(ex, cxt, Usage::Sizeof)?,
+    };
+    match cxt.get_type_size(&ty) {
+        0 => Err(ErrKind::TypeErr),
+        sz => {
+            cxt.table_mut().set_size(n.id, sz);
+            Ok(Type::Int)
+        }
+    }
+}
+
+// callee is any expression of function pointer type
+fn analyze_indirect(n: &IndirectCallNd, cxt: &mut Context) -> Result<Type, ErrKind> {
+    match n.callee.analyze(cxt)? {
+        Type::Ptr(t) => analyze_args(cxt, &t, &n.params),
+        _ => Err(ErrKind::TypeErr),
+    }
+}
+
+// params of a call against the func type, gives the return type
+fn analyze_args(cxt: &mut Context, fty: &Type, params: &[ExprNd]) -> Result<Type, ErrKind> {
+    let (v, variadic) = match fty {
+        Type::Func(v, variadic) => (v, *variadic),
+        _ => return Err(ErrKind::TypeErr),
+    };
+    let fixed = v.len() - 1;
+    if params.len() < fixed || params.len() > fixed && !variadic {
+        return Err(ErrKind::TypeErr);
+    }
+    for (idx, p) in params.iter().enumerate() {
+        let ty = p.analyze(cxt)?;
+        if idx < fixed {
+            convert(cxt, &v[idx], &ty, p)?;
+        } else if !ty.is_scalar() {
+            // variadic args take one cell each
+            return Err(ErrKind::TypeErr);
+        }
+    }
+    Ok(v.last().unwrap().clone())
+}
+
+impl Analyzer for VaNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
-        let mut st = Vec::new();
-        for it in self.stack.iter() {
-            match it {
-                CalcItem::Op(op) => {
-                    let num = get_op_param_num(op.clone());
-                    for _ in 0..num {
-                        if Type::Int != st.pop().unwrap() {
-                            return Err(ErrKind::TypeErr);
-                        }
-                    }
-                    st.push(Type::Int);
-                }
-                CalcItem::Factor(f) => {
-                    st.push(f.analyze(cxt)?);
+        let ap = match self {
+            VaNd::Start(ap, _) | VaNd::Arg(ap, _) | VaNd::End(ap) => ap,
+        };
+        let va_list = Type::Ptr(Box::new(Type::Void));
+        if ap.analyze(cxt)? != va_list || !cxt.is_var(cxt.table().symbol(ap.id)) {
+            return Err(ErrKind::TypeErr);
+        }
+        match self {
+            VaNd::Start(_, last) => {
+                last.analyze(cxt)?;
+                match cxt.get_type_by_id(cxt.get_cur_func_id())? {
+                    Type::Func(_, true) => Ok(Type::Void),
+                    _ => Err(ErrKind::TypeErr),
                 }
             }
+            VaNd::Arg(_, ty) if ty.unqualified().is_scalar() => Ok(ty.unqualified().clone()),
+            VaNd::Arg(_, _) => Err(ErrKind::TypeErr),
+            VaNd::End(_) => Ok(Type::Void),
         }
-        if st.len() != 1 {
-            Err(ErrKind::TypeErr)
-        } else {
-            Ok(st.last().unwrap().clone())
+    }
+}
+
+// fill array bounds given by constant expressions, outer first
+fn resolve_dims(cxt: &mut Context, ty: &Type, bounds: &[Option<ExprNd>]) -> Result<Type, ErrKind> {
+    match (ty, bounds.split_first()) {
+        (Type::Array(t, n), Some((b, rest))) => {
+            let t = resolve_dims(cxt, t, rest)?;
+            let n = match b {
+                Some(ex) => {
+                    if !ex.analyze(cxt)?.is_integer() {
+                        return Err(ErrKind::TypeErr);
+                    }
+                    match cxt.retrieve_const(ex) {
+                        Some(n) if n > 0 => n as usize,
+                        Some(_) => return Err(ErrKind::TypeErr),
+                        None => return Err(ErrKind::GlobalNeedConst),
+                    }
+                }
+                None => *n,
+            };
+            Ok(Type::Array(Box::new(t), n))
         }
+        _ => Ok(ty.clone()),
+    }
+}
+
+impl Analyzer for ExprNd {
+    fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
+        analyze_calc(self, cxt, Usage::Value)
     }
 }
 
 impl Analyzer for VarNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         let id = cxt.fetch(&self.name)?;
-        self.set_id(id);
-        Ok(cxt.get_type_by_id(id)?)
+        cxt.table_mut().set_symbol(self.id, id);
+        cxt.get_type_by_id(id)
     }
 }
 
 impl Analyzer for AssignNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         let ty = self.expr.analyze(cxt)?;
-        if ty != self.var.analyze(cxt)? {
-            Err(ErrKind::TypeErr)
-        } else {
-            Ok(Type::Void)
+        let lty = analyze_calc(&self.lhs, cxt, Usage::Lvalue)?;
+        if lty.is_const() || has_const_member(cxt, &lty) {
+            return Err(ErrKind::AssignConst);
         }
+        convert(cxt, &lty, &ty, &self.expr)?;
+        Ok(Type::Void)
     }
 }
 
 impl Analyzer for DeclareNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
-        if self.expr.is_some() {
-            if self.ty != self.expr.as_ref().unwrap().analyze(cxt)? {
-                return Err(ErrKind::TypeErr);
+        let decl_ty = resolve_dims(cxt, &self.ty, &self.bounds)?;
+        if let Some(ex) = &self.expr {
+            let ty = ex.analyze(cxt)?;
+            match (&decl_ty, ex.try_to_str()) {
+                (Type::Array(t, n), Some(s)) => {
+                    if *t.unqualified() != Type::Char || s.chars().count() >= *n {
+                        return Err(ErrKind::TypeErr);
+                    }
+                }
+                _ => convert(cxt, &decl_ty, &ty, ex)?,
+            }
+        }
+        if !self.list.is_empty() {
+            // a braced list fills an array of scalars, the rest is zeroed
+            let t = match &decl_ty {
+                Type::Array(t, n) if t.unqualified().is_scalar() && self.list.len() <= *n => t,
+                _ => return Err(ErrKind::TypeErr),
+            };
+            for ex in self.list.iter() {
+                let ty = ex.analyze(cxt)?;
+                convert(cxt, t, &ty, ex)?;
+            }
+        }
+        let id = cxt.declare_var(&self.var.name, &decl_ty, self.storage)?;
+        cxt.table_mut().set_symbol(self.var.id, id);
+        if self.storage == StorageClass::Extern || !cxt.is_in_memory(id) {
+            return Ok(Type::Void);
+        }
+        // memory is initialized before running
+        let is_str = self
+            .expr
+            .as_ref()
+            .map_or(false, |e| e.try_to_str().is_some());
+        let is_const = |ex: &ExprNd| cxt.retrieve_const(ex).is_some() || cxt.is_func_designator(ex);
+        if !is_str && !self.expr.iter().chain(self.list.iter()).all(is_const) {
+            return Err(ErrKind::GlobalNeedConst);
+        }
+        // const ints can be folded into later initializers
+        if decl_ty.is_const() && decl_ty.unqualified().is_integer() {
+            if let Some(v) = cxt.retrieve_init_const(self) {
+                cxt.bind_const(id, v);
             }
         }
-        let id = cxt.declare_var(&self.var.name, &self.ty)?;
-        self.var.set_id(id);
         Ok(Type::Void)
     }
 }
@@ -80,7 +476,12 @@ impl Analyzer for StmtNd {
             StmtNd::Assign(n) => n.analyze(cxt),
             StmtNd::Declare(n) => n.analyze(cxt),
             StmtNd::Expr(n) => n.analyze(cxt),
-            StmtNd::Print(n) => n.analyze(cxt),
+            StmtNd::Print(n) => {
+                if !n.analyze(cxt)?.unqualified().is_scalar() {
+                    return Err(ErrKind::TypeErr);
+                }
+                Ok(Type::Void)
+            }
             _ => Ok(Type::Void),
         }
     }
@@ -89,14 +490,14 @@ impl Analyzer for StmtNd {
 impl Analyzer for IfNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         let scope_id = cxt.enter_scope();
-        self.set_id(scope_id);
-        if Type::Int != self.expr.analyze(cxt)? {
+        cxt.table_mut().set_scope(self.id, scope_id);
+        if !self.expr.analyze(cxt)?.is_scalar() {
             return Err(ErrKind::TypeErr);
         }
         self.item.analyze(cxt)?;
         cxt.exit_scope();
-        if self.els.is_some() {
-            self.els.as_ref().unwrap().analyze(cxt)?;
+        if let Some(els) = &self.els {
+            els.analyze(cxt)?;
         }
         Ok(Type::Void)
     }
@@ -114,8 +515,8 @@ impl Analyzer for ElsNd {
 impl Analyzer for WhileNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         let scope_id = cxt.enter_loop_scope();
-        self.set_id(scope_id);
-        if Type::Int != self.expr.analyze(cxt)? {
+        cxt.table_mut().set_scope(self.id, scope_id);
+        if !self.expr.analyze(cxt)?.is_scalar() {
             return Err(ErrKind::TypeErr);
         }
         self.item.analyze(cxt)?;
@@ -128,8 +529,9 @@ impl Analyzer for BreakNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         match cxt.get_loop_scope() {
             Some(id) => {
-                self.set_id(id);
-                self.set_pop_off(cxt.get_off_by_id(id));
+                let off = cxt.get_off_by_id(id);
+                cxt.table_mut().set_scope(self.id, id);
+                cxt.table_mut().set_pop_off(self.id, off);
                 Ok(Type::Void)
             }
             None => Err(ErrKind::JumpNoLoop),
@@ -141,8 +543,9 @@ impl Analyzer for ContinueNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         match cxt.get_loop_scope() {
             Some(id) => {
-                self.set_id(id);
-                self.set_pop_off(cxt.get_off_by_id(id));
+                let off = cxt.get_off_by_id(id);
+                cxt.table_mut().set_scope(self.id, id);
+                cxt.table_mut().set_pop_off(self.id, off);
                 Ok(Type::Void)
             }
             None => Err(ErrKind::JumpNoLoop),
@@ -158,19 +561,23 @@ impl Analyzer for ReturnNd {
             Some(n) => {
                 let ty = n.analyze(cxt)?;
                 match func_ty {
-                    Type::Func(vec) => {
-                        if ty != vec.last().unwrap().clone() {
+                    Type::Func(vec, _) => {
+                        let ret_ty = vec.last().unwrap();
+                        if *ret_ty == Type::Void {
                             return Err(ErrKind::TypeErr);
                         }
+                        convert(cxt, ret_ty, &ty, n)?;
+                        let sz = cxt.get_type_size(ret_ty);
+                        cxt.table_mut().set_ret_size(self.id, sz);
                     }
                     _ => panic!("Func type err"),
                 }
-                self.set_sz(get_type_size(ty));
             }
             None => match func_ty {
-                Type::Func(vec) => {
+                Type::Func(vec, _) => {
                     let ty = vec.last().unwrap().clone();
-                    self.set_sz(get_type_size(ty));
+                    let sz = cxt.get_type_size(&ty);
+                    cxt.table_mut().set_ret_size(self.id, sz);
                 }
                 _ => panic!("Func type err"),
             },
@@ -196,7 +603,7 @@ impl Analyzer for ItemNd {
 impl Analyzer for BlockNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         let scope_id = cxt.enter_scope();
-        self.set_id(scope_id);
+        cxt.table_mut().set_scope(self.id, scope_id);
         for item in self.items.iter() {
             item.analyze(cxt)?;
         }
@@ -211,18 +618,18 @@ impl Analyzer for FuncNd {
         let id = if self.is_impl() {
             cxt.impl_fn(name, &self.func_ty())?
         } else {
-            cxt.declare_fn(name, &self.func_ty())?
+            cxt.declare_proto(name, &self.func_ty())?
         };
-        self.var.set_id(id);
+        cxt.table_mut().set_symbol(self.var.id, id);
         if self.is_impl() {
-            cxt.enter_scope();
+            cxt.enter_func(id);
             for (t, v) in self.params.iter() {
                 if v.is_none() {
                     panic!("not parse well");
                 }
                 let v = v.as_ref().unwrap();
-                let id = cxt.declare_var(&v.name, t)?;
-                v.set_id(id);
+                let id = cxt.declare_var(&v.name, t, StorageClass::Auto)?;
+                cxt.table_mut().set_symbol(v.id, id);
             }
             self.block.as_ref().unwrap().analyze(cxt)?;
             cxt.exit_scope();
@@ -235,22 +642,56 @@ impl Analyzer for FuncCallNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         let name = &self.var.name;
         let id = cxt.fetch(name)?;
-        self.var.set_id(id);
+        cxt.table_mut().set_symbol(self.var.id, id);
         let ty = cxt.get_type_by_id(id)?;
-        match ty {
-            Type::Func(v) => {
-                if self.params.len() + 1 != v.len() {
-                    Err(ErrKind::TypeErr)
-                } else {
-                    for (idx, p) in self.params.iter().enumerate() {
-                        if p.analyze(cxt)? != v[idx] {
-                            return Err(ErrKind::TypeErr);
-                        }
-                    }
-                    Ok(v.last().unwrap().clone())
+        match ty.unqualified() {
+            // a function pointer var is called through its value
+            Type::Ptr(t) => analyze_args(cxt, t, &self.params),
+            t => analyze_args(cxt, t, &self.params),
+        }
+    }
+}
+
+impl Analyzer for StructNd {
+    fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
+        let mut fields: Vec<(Type, String)> = Vec::new();
+        for n in self.fields.iter() {
+            fields.push((resolve_dims(cxt, &n.ty, &n.bounds)?, n.var.name.clone()));
+        }
+        cxt.declare_struct(&self.name, &fields)?;
+        Ok(Type::Void)
+    }
+}
+
+impl Analyzer for EnumNd {
+    fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
+        if let Some(name) = &self.name {
+            cxt.declare_enum(name)?;
+        }
+        let mut next = 0;
+        for (v, ex) in self.items.iter() {
+            if let Some(ex) = ex {
+                if ex.analyze(cxt)? != Type::Int {
+                    return Err(ErrKind::TypeErr);
                 }
+                next = match cxt.retrieve_const(ex) {
+                    Some(n) => n,
+                    None => return Err(ErrKind::GlobalNeedConst),
+                };
             }
-            _ => panic!("Should have func type"),
+            let id = cxt.declare_const(&v.name, next)?;
+            cxt.table_mut().set_symbol(v.id, id);
+            next += 1;
+        }
+        Ok(Type::Void)
+    }
+}
+
+impl Analyzer for TypedefNd {
+    fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
+        match &self.def {
+            Some(n) => n.analyze(cxt),
+            None => Ok(Type::Void),
         }
     }
 }
@@ -258,21 +699,18 @@ impl Analyzer for FuncCallNd {
 impl Analyzer for GItemNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
         match self {
+            GItemNd::Struct(n) => n.analyze(cxt),
+            GItemNd::Enum(n) => n.analyze(cxt),
+            GItemNd::Typedef(n) => n.analyze(cxt),
             GItemNd::Func(n) => n.analyze(cxt),
-            GItemNd::Declare(n) => {
-                n.analyze(cxt)?;
-                if n.ty == Type::Int && n.try_retrieve_const().is_none() {
-                    Err(ErrKind::GlobalNeedConst)
-                } else {
-                    Ok(Type::Void)
-                }
-            }
+            GItemNd::Declare(n) => n.analyze(cxt),
         }
     }
 }
 
 impl Analyzer for RootNd {
     fn analyze(&self, cxt: &mut Context) -> Result<Type, ErrKind> {
+        cxt.set_root(self.id);
         for item in self.items.iter() {
             item.analyze(cxt)?;
         }
@@ -1,38 +1,66 @@
 mod context;
 mod imp;
 
-use super::analyzer::{analyze, Analyzer};
-use crate::core::types::ErrKind;
-use context::Context;
-pub use context::Program;
-
-pub trait Compiler: Analyzer {
-    fn compile(&self, prog: &mut Context);
-}
+use super::analyzer::{analyze, analyze_with_natives, Semantic};
+use super::parser::parse_source;
+use crate::core::types::visit::Visitor;
+use crate::core::types::{ErrKind, Error, RootNd, SemanticError, Type};
+pub use context::{Context, FuncSymbol, Program};
 
+#[allow(dead_code)]
 pub fn compile(code: &str) -> Result<Program, ErrKind> {
     let (nd, info) = analyze(code)?;
+    Ok(compile_tree(&nd, info))
+}
+
+// same as compile, with the failing stage in the error
+pub fn compile_source(code: &str) -> Result<Program, Error> {
+    compile_with_natives(code, &[])
+}
+
+// calls to the natives go through the table of the vm, see VM::load
+pub fn compile_with_natives(code: &str, natives: &[(String, Type)]) -> Result<Program, Error> {
+    let nd = parse_source::<RootNd>(code)?;
+    let info = analyze_with_natives(&nd, natives).map_err(|kind| SemanticError { kind })?;
+    Ok(compile_tree(&nd, info))
+}
+
+// `info` has to be the analysis of `nd`, its tables are keyed by the nodes
+pub fn compile_root(nd: &RootNd, info: Semantic) -> Result<Program, Error> {
+    if info.root != Some(nd.id) {
+        let kind = ErrKind::ForeignTree;
+        return Err(SemanticError { kind }.into());
+    }
+    Ok(compile_tree(nd, info))
+}
+
+fn compile_tree(nd: &RootNd, info: Semantic) -> Program {
     let mut cxt = Context::new(info);
-    nd.compile(&mut cxt);
-    Ok(Program::new(cxt))
+    cxt.visit_root(nd);
+    Program::new(cxt)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::core::analyzer::analyze_root;
+    use crate::core::parser::parse;
+    use crate::core::types::RootNd;
     use crate::utils::load_code_from_file;
+    use std::sync::Arc;
+    use std::thread;
 
     #[test]
     fn test_compile_basic() {
         let code = load_code_from_file("test_cfiles/vm/basic.c");
         let prog = super::compile(&code);
-        if prog.is_err() {
-            println!("{:?}", prog.err());
-        } else {
-            let prog = prog.unwrap();
-            for c in prog.codes {
-                println!("{:?}", c);
+        match prog {
+            Ok(prog) => {
+                for c in prog.codes {
+                    println!("{:?}", c);
+                }
+                println!("Big ok");
             }
-            println!("Big ok");
+            Err(e) => println!("{:?}", e),
         }
     }
 
@@ -40,14 +68,14 @@ mod tests {
     fn test_compile_while() {
         let code = load_code_from_file("test_cfiles/vm/while.c");
         let prog = super::compile(&code);
-        if prog.is_err() {
-            println!("{:?}", prog.err());
-        } else {
-            let prog = prog.unwrap();
-            for c in prog.codes {
-                println!("{:?}", c);
+        match prog {
+            Ok(prog) => {
+                for c in prog.codes {
+                    println!("{:?}", c);
+                }
+                println!("Big ok");
             }
-            println!("Big ok");
+            Err(e) => println!("{:?}", e),
         }
     }
 
@@ -55,14 +83,37 @@ mod tests {
     fn test_compile_if() {
         let code = load_code_from_file("test_cfiles/vm/if.c");
         let prog = super::compile(&code);
-        if prog.is_err() {
-            println!("{:?}", prog.err());
-        } else {
-            let prog = prog.unwrap();
-            for c in prog.codes {
-                println!("{:?}", c);
+        match prog {
+            Ok(prog) => {
+                for c in prog.codes {
+                    println!("{:?}", c);
+                }
+                println!("Big ok");
             }
-            println!("Big ok");
+            Err(e) => println!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_compile_shared_tree() {
+        // one tree, analyzed twice and from several threads
+        let code = load_code_from_file("test_cfiles/vm/func_ptr.c");
+        let nd = Arc::new(parse::<RootNd>(&code).unwrap());
+        let expected = super::compile_root(&nd, analyze_root(&nd).unwrap())
+            .unwrap()
+            .codes;
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let nd = Arc::clone(&nd);
+                thread::spawn(move || {
+                    super::compile_root(&nd, analyze_root(&nd).unwrap())
+                        .unwrap()
+                        .codes
+                })
+            })
+            .collect();
+        for h in handles {
+            assert_eq!(expected, h.join().unwrap());
         }
     }
 }
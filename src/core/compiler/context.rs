@@ -1,91 +1,194 @@
 use crate::core::analyzer::Semantic;
-use crate::core::types::{Code, CodeAddr, Layout, MemAddr, Type};
+use crate::core::types::{
+    calc_trunc, get_type_size, Code, CodeAddr, ExprNd, Layout, MemAddr, NodeTable, Type, VarNd,
+    WarnKind,
+};
 
 use std::collections::HashMap;
 
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
-    pub memory: Vec<i32>,
+    pub memory: Vec<i32>, // globals and static locals, then read-only string literals
+    pub rodata: usize,
     pub codes: Vec<Code>,
+    pub warnings: Vec<WarnKind>,
     pub start_pc: usize,
+    pub funcs: Vec<FuncSymbol>, // implemented functions, by pc
+}
+
+// entry of an implemented function
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FuncSymbol {
+    pub name: String,
+    pub pc: usize,
+    pub ty: Type,
 }
 
 impl Program {
     pub fn new(cxt: Context) -> Self {
-        // Link
-        let mut codes = Vec::new();
-        macro_rules! link_address {
-            ($addr: ident) => {
-                match $addr {
-                    CodeAddr::NameStart(id) => {
-                        CodeAddr::Direct(cxt.code_layout.get(id).unwrap().offset as usize)
-                    }
-                    CodeAddr::NameEnd(id) => {
-                        CodeAddr::Direct(cxt.code_layout.get(id).unwrap().last() as usize)
-                    }
-                    _ => $addr.clone(),
-                }
-            };
-        }
-        for c in cxt.codes.iter() {
-            let cc = match c {
-                Code::Call(addr, n) => Code::Call(link_address!(addr), *n),
-                Code::Jump(addr) => Code::Jump(link_address!(addr)),
-                Code::CondJump(addr) => Code::CondJump(link_address!(addr)),
-                _ => c.clone(),
-            };
-            codes.push(cc);
-        }
-
+        let codes = cxt.link();
+        let funcs = cxt.symbols();
         let pc = cxt.code_layout[&cxt.s_info.main_func_id].offset;
         Program {
-            memory: cxt.memory,
-            codes: codes,
+            memory: cxt.link_memory(),
+            rodata: cxt.rodata,
+            codes,
+            warnings: cxt.s_info.warnings,
             start_pc: pc,
+            funcs,
+        }
+    }
+
+    pub fn func(&self, name: &str) -> Option<&FuncSymbol> {
+        self.funcs.iter().find(|f| f.name == name)
+    }
+
+    // only the null cell, it returns at once
+    pub fn empty() -> Self {
+        Program {
+            memory: vec![0],
+            rodata: 1,
+            codes: vec![Code::Ret(0)],
+            warnings: Vec::new(),
+            start_pc: 0,
+            funcs: Vec::new(),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Context {
     code_layout: HashMap<u32, Layout>,
     s_info: Semantic,
     codes: Vec<Code>,
     memory: Vec<i32>,
+    rodata: usize,
+    strings: HashMap<String, usize>, // literal -> address
+    func_cells: Vec<(usize, u32)>,   // memory holding the address of a func
     func_id: u32,
+    funcs: Vec<(u32, String)>, // implemented funcs
 }
 
 impl Context {
     pub fn new(info: Semantic) -> Self {
+        let global_size = info.mem_layout[0].size;
         Context {
             code_layout: HashMap::new(),
             s_info: info,
-            codes: Vec::new(),
-            memory: Vec::new(),
+            // no function starts at 0, so it stays a null function pointer
+            codes: vec![Code::Ret(0)],
+            memory: vec![0; global_size],
+            rodata: global_size,
+            strings: HashMap::new(),
+            func_cells: Vec::new(),
             func_id: 0,
+            funcs: Vec::new(),
+        }
+    }
+
+    // names, code addresses and constants of a later analysis of the same
+    // tree, the globals it added start zeroed
+    pub fn update(&mut self, info: Semantic) {
+        let global_size = info.mem_layout[0].size;
+        if global_size > self.memory.len() {
+            self.memory.resize(global_size, 0);
+        }
+        self.rodata = self.memory.len();
+        self.s_info = info;
+    }
+
+    // globals and literals so far
+    pub fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+
+    // program of the codes so far, starting at func `entry`; an entry
+    // without code starts at the null code, which returns at once
+    pub fn program(&self, entry: u32) -> Program {
+        Program {
+            memory: self.link_memory(),
+            rodata: self.rodata,
+            codes: self.link(),
+            warnings: self.s_info.warnings.clone(),
+            start_pc: self.code_layout.get(&entry).map_or(0, |l| l.offset),
+            funcs: self.symbols(),
         }
     }
 
-    pub fn enter_func(&mut self, id: u32) {
+    // named addresses become direct, a func implemented again is called
+    // at its last code
+    fn link(&self) -> Vec<Code> {
+        let link_address = |addr: &CodeAddr| match addr {
+            CodeAddr::NameStart(id) => CodeAddr::Direct(self.code_layout[id].offset),
+            CodeAddr::NameEnd(id) => CodeAddr::Direct(self.code_layout[id].last()),
+            _ => *addr,
+        };
+        self.codes
+            .iter()
+            .map(|c| match c {
+                Code::PushFunc(addr) => Code::PushFunc(link_address(addr)),
+                Code::Call(addr, n) => Code::Call(link_address(addr), *n),
+                Code::Jump(addr) => Code::Jump(link_address(addr)),
+                Code::CondJump(addr) => Code::CondJump(link_address(addr)),
+                _ => *c,
+            })
+            .collect()
+    }
+
+    // globals set to a func get its first code, null until it has one
+    fn link_memory(&self) -> Vec<i32> {
+        let mut memory = self.memory.clone();
+        for (off, id) in self.func_cells.iter() {
+            memory[*off] = self.code_layout.get(id).map_or(0, |l| l.offset as i32);
+        }
+        memory
+    }
+
+    fn symbols(&self) -> Vec<FuncSymbol> {
+        let mut funcs: Vec<FuncSymbol> = self
+            .funcs
+            .iter()
+            .map(|(id, name)| FuncSymbol {
+                name: name.clone(),
+                pc: self.code_layout[id].offset,
+                ty: self.s_info.funcs[id].ty.clone(),
+            })
+            .collect();
+        funcs.sort_by_key(|f| f.pc);
+        funcs
+    }
+
+    pub fn enter_func(&mut self, id: u32, name: &str) {
         self.func_id = id;
+        if !self.funcs.iter().any(|(f, _)| *f == id) {
+            self.funcs.push((id, name.to_owned()));
+        }
         self.enter(id);
     }
 
     pub fn exit_func(&mut self) {
         self.exit(self.func_id);
         match self.s_info.funcs[&self.func_id].ty.clone() {
-            Type::Func(v) => {
-                let ret_ty = v.last().unwrap();
-                if ret_ty.clone() == Type::Int {
-                    self.add_code(Code::PushValue(0));
-                    self.add_code(Code::Ret(1));
-                } else {
-                    self.add_code(Code::Ret(0));
-                }
+            Type::Func(v, _) => {
+                let sz = self.get_type_size(v.last().unwrap());
+                self.add_zeros(sz);
+                self.ret(sz);
             }
             _ => panic!("not func type"),
         }
         self.func_id = 0;
     }
 
+    // variadic funcs also drop the args passed under the frame
+    pub fn ret(&mut self, sz: usize) {
+        match self.s_info.funcs[&self.func_id].ty {
+            Type::Func(_, true) => self.add_code(Code::RetVariadic(sz)),
+            _ => self.add_code(Code::Ret(sz)),
+        }
+    }
+
     pub fn get_cur(&self) -> usize {
         self.codes.len()
     }
@@ -110,9 +213,20 @@ impl Context {
         self.codes.push(code);
     }
 
+    pub fn add_zeros(&mut self, sz: usize) {
+        match sz {
+            0 => {}
+            1 => self.add_code(Code::PushValue(0)),
+            _ => self.add_code(Code::Reserve(sz)),
+        }
+    }
+
+    pub fn is_in_memory(&self, id: u32) -> bool {
+        self.s_info.vars[&id].in_memory()
+    }
+
     fn get_var_addr(&self, id: u32) -> MemAddr {
-        let global = self.s_info.vars[&id].is_global();
-        if global {
+        if self.s_info.vars[&id].in_memory() {
             MemAddr::Direct(self.s_info.mem_layout[id as usize].offset)
         } else {
             MemAddr::Indirect(self.s_info.mem_layout[id as usize].offset)
@@ -120,25 +234,160 @@ impl Context {
     }
 
     pub fn push(&mut self, id: u32) {
-        let code = Code::Push(self.get_var_addr(id));
+        let code = match self.s_info.consts.get(&id) {
+            Some(v) => Code::PushValue(*v),
+            None if self.s_info.funcs.contains_key(&id) => Code::PushFunc(CodeAddr::NameStart(id)),
+            None => Code::Push(self.get_var_addr(id)),
+        };
         self.codes.push(code);
     }
 
+    // push the var's address, load `load` cells if used as value
+    pub fn push_var(&mut self, id: u32, load: usize) {
+        if load == 1 || !self.s_info.vars.contains_key(&id) {
+            self.push(id);
+        } else {
+            let code = Code::PushAddr(self.get_var_addr(id));
+            self.codes.push(code);
+            if load > 1 {
+                self.add_code(Code::Load(load));
+            }
+        }
+    }
+
+    pub fn get_type_size(&self, ty: &Type) -> usize {
+        get_type_size(ty.clone(), &self.s_info.structs)
+    }
+
+    pub fn get_var_type(&self, id: u32) -> Type {
+        self.s_info.vars[&id].ty.clone()
+    }
+
+    pub fn get_var_size(&self, id: u32) -> usize {
+        self.get_type_size(&self.s_info.vars[&id].ty)
+    }
+
     pub fn pop(&mut self, id: u32) {
         let code = Code::PopMov(self.get_var_addr(id));
         self.codes.push(code);
     }
 
-    pub fn call(&mut self, id: u32) {
-        let num = match self.s_info.funcs[&id].ty.clone() {
-            Type::Func(v) => v.len() - 1,
+    // type of a func, or of the func pointed to by a var
+    pub fn get_func_type(&self, id: u32) -> Type {
+        match self.s_info.funcs.get(&id) {
+            Some(f) => f.ty.clone(),
+            None => match self.get_var_type(id).unqualified() {
+                Type::Ptr(t) => *t.clone(),
+                _ => panic!("Not func pointer type"),
+            },
+        }
+    }
+
+    // cells taken by the fixed params of a func type
+    fn params_size(&self, ty: &Type) -> usize {
+        match ty {
+            Type::Func(v, _) => v[..v.len() - 1].iter().map(|t| self.get_type_size(t)).sum(),
             _ => panic!("Not func type"),
+        }
+    }
+
+    pub fn call(&mut self, id: u32) {
+        let finfo = match self.s_info.funcs.get(&id) {
+            Some(f) => f,
+            None => {
+                // function pointer var
+                self.push(id);
+                let ty = self.get_var_type(id);
+                self.call_indirect(&ty);
+                return;
+            }
         };
+        if let Some(b) = finfo.builtin {
+            self.add_code(Code::Builtin(b));
+            return;
+        }
+        let num = self.params_size(&finfo.ty);
+        if let Some(idx) = finfo.native {
+            self.add_code(Code::CallNative(idx, num));
+            return;
+        }
         let code = Code::Call(CodeAddr::NameStart(id), num);
         self.codes.push(code);
     }
 
-    pub fn add_memory(&mut self, v: i32) {
-        self.memory.push(v);
+    // the target is pushed after the params
+    pub fn call_indirect(&mut self, ty: &Type) {
+        let num = match ty.unqualified() {
+            Type::Ptr(t) => self.params_size(t),
+            _ => panic!("Not func pointer type"),
+        };
+        self.add_code(Code::CallIndirect(num));
+    }
+
+    pub fn table(&self) -> &NodeTable {
+        &self.s_info.table
+    }
+
+    // var, func or const a name resolved to
+    pub fn symbol(&self, v: &VarNd) -> u32 {
+        self.s_info.table.symbol(v.id)
+    }
+
+    pub fn expr_type(&self, ex: &ExprNd) -> Type {
+        self.s_info.table.expr_type(ex.id)
+    }
+
+    pub fn set_memory(&mut self, id: u32, v: i32) {
+        let off = self.s_info.mem_layout[id as usize].offset;
+        self.memory[off] = v;
+    }
+
+    // cell `i` of a global from a constant or a func, of type `ty`
+    pub fn init_memory(&mut self, id: u32, i: usize, ex: &ExprNd, ty: &Type) {
+        let off = self.s_info.mem_layout[id as usize].offset + i;
+        let func = ex
+            .try_to_designator()
+            .map(|v| self.symbol(v))
+            .filter(|f| self.s_info.funcs.contains_key(f));
+        match func {
+            Some(f) => self.func_cells.push((off, f)),
+            None => {
+                let v = ex
+                    .try_retrieve_const(&self.s_info.consts, &self.s_info.table)
+                    .unwrap();
+                self.memory[off] = match ty.unqualified() {
+                    Type::Char => calc_trunc(v, 8),
+                    _ => v,
+                };
+            }
+        }
+    }
+
+    // address of the literal, equal literals share the bytes
+    pub fn add_string(&mut self, s: &str) -> usize {
+        if let Some(addr) = self.strings.get(s) {
+            return *addr;
+        }
+        let addr = self.memory.len();
+        self.memory.extend(s.chars().map(|c| c as u8 as i8 as i32));
+        self.memory.push(0);
+        self.strings.insert(s.to_owned(), addr);
+        addr
+    }
+
+    // global char array gets a copy, char pointer the literal's address
+    pub fn set_memory_str(&mut self, id: u32, s: &str) {
+        match self.s_info.vars[&id].ty {
+            Type::Array(_, _) => {
+                let off = self.s_info.mem_layout[id as usize].offset;
+                for (i, c) in s.chars().enumerate() {
+                    self.memory[off + i] = c as u8 as i8 as i32;
+                }
+            }
+            _ => {
+                let addr = self.add_string(s);
+                self.set_memory(id, addr as i32);
+            }
+        }
     }
 }
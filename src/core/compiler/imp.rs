@@ -1,214 +1,289 @@
-use super::{Compiler, Context};
+use super::Context;
 use crate::core::types::nodes::*;
-use crate::core::types::{CalcItem, Code, CodeAddr, Type, Value};
+use crate::core::types::visit::Visitor;
+use crate::core::types::{CalcItem, Code, CodeAddr, MemAddr, Op, StorageClass, Type, Value};
 
-impl Compiler for FactorNd {
-    fn compile(&self, cxt: &mut Context) {
-        match self {
-            FactorNd::Var(n) => n.compile(cxt),
+// Code generation, roots, globals, items and else branches keep the default walk
+impl Visitor for Context {
+    fn visit_factor(&mut self, n: &FactorNd) {
+        match n {
+            FactorNd::Var(n) => self.visit_var(n),
             FactorNd::Value(v) => {
                 if let Value::Int(num) = v {
-                    cxt.add_code(Code::PushValue(*num));
+                    self.add_code(Code::PushValue(*num));
                 }
             }
-            FactorNd::Func(n) => n.compile(cxt),
+            FactorNd::Str(s) => {
+                let addr = self.add_string(s);
+                self.add_code(Code::PushAddr(MemAddr::Direct(addr)));
+            }
+            FactorNd::Func(n) => self.visit_func_call(n),
+            FactorNd::Indirect(n) => self.visit_indirect_call(n),
+            FactorNd::Sizeof(n) => self.visit_sizeof(n),
+            FactorNd::Va(n) => self.visit_va(n),
+        }
+    }
+
+    fn visit_indirect_call(&mut self, n: &IndirectCallNd) {
+        let ty = self.expr_type(&n.callee);
+        if let Type::Ptr(t) = &ty {
+            compile_args(self, t.as_ref(), &n.params);
+        }
+        self.visit_expr(&n.callee);
+        self.call_indirect(&ty);
+    }
+
+    fn visit_sizeof(&mut self, n: &SizeofNd) {
+        self.add_code(Code::PushValue(self.table().size(n.id) as i32));
+    }
+
+    fn visit_va(&mut self, n: &VaNd) {
+        match n {
+            VaNd::Start(ap, _) => {
+                self.add_code(Code::VaStart);
+                self.pop(self.symbol(ap));
+            }
+            VaNd::Arg(ap, ty) => {
+                // read the arg, then step over it
+                let sz = self.get_type_size(ty);
+                self.push(self.symbol(ap));
+                self.add_code(Code::VaArg(sz));
+                self.add_code(Code::Load(sz));
+                self.push(self.symbol(ap));
+                self.add_code(Code::PushValue(sz as i32));
+                self.add_code(Code::Op(Op::Add));
+                self.pop(self.symbol(ap));
+            }
+            VaNd::End(ap) => {
+                self.add_code(Code::PushValue(0));
+                self.pop(self.symbol(ap));
+            }
         }
     }
-}
 
-impl Compiler for ExprNd {
-    fn compile(&self, cxt: &mut Context) {
-        for it in self.stack.iter() {
+    fn visit_expr(&mut self, n: &ExprNd) {
+        let infos = self.table().infos(n.id).to_vec();
+        for (it, info) in n.stack.iter().zip(infos.iter()) {
             match it {
+                CalcItem::Op(Op::Deref | Op::AddrOf) => {}
+                CalcItem::Op(Op::Index) => {
+                    self.add_code(Code::Op(Op::Add));
+                }
                 CalcItem::Op(op) => {
-                    cxt.add_code(Code::Op(op.clone()));
+                    self.add_code(Code::Op(*op));
+                }
+                CalcItem::Member(_) => {
+                    if info.off > 0 {
+                        self.add_code(Code::PushValue(info.off as i32));
+                        self.add_code(Code::Op(Op::Add));
+                    }
+                }
+                CalcItem::Factor(FactorNd::Var(v)) => {
+                    self.push_var(self.symbol(v), info.load);
                 }
                 CalcItem::Factor(f) => {
-                    f.compile(cxt);
+                    self.visit_factor(f);
                 }
+                CalcItem::Cast(_) => {}
+            }
+            if info.load > 0 && !matches!(it, CalcItem::Factor(FactorNd::Var(_))) {
+                self.add_code(Code::Load(info.load));
+            }
+            if info.scale > 1 {
+                self.add_code(Code::PushValue(info.scale as i32));
+                self.add_code(Code::Op(Op::Multiply));
+            }
+            if info.div > 1 {
+                self.add_code(Code::PushValue(info.div as i32));
+                self.add_code(Code::Op(Op::Divide));
+            }
+            if info.trunc > 0 {
+                self.add_code(Code::Trunc(info.trunc));
+            }
+            if info.drop > 0 {
+                self.add_code(Code::Pop(info.drop));
             }
         }
     }
-}
 
-impl Compiler for VarNd {
-    fn compile(&self, cxt: &mut Context) {
-        cxt.push(self.get_id());
+    fn visit_var(&mut self, n: &VarNd) {
+        self.push(self.symbol(n));
     }
-}
 
-impl Compiler for AssignNd {
-    fn compile(&self, cxt: &mut Context) {
-        self.expr.compile(cxt);
-        cxt.pop(self.var.get_id());
+    fn visit_assign(&mut self, n: &AssignNd) {
+        match n.lhs.try_to_var() {
+            Some(v) if self.get_var_size(self.symbol(&v)) == 1 => {
+                self.visit_expr(&n.expr);
+                self.pop(self.symbol(&v));
+            }
+            _ => {
+                self.visit_expr(&n.lhs);
+                self.visit_expr(&n.expr);
+                let sz = self.get_type_size(&self.expr_type(&n.lhs));
+                self.add_code(Code::Store(sz));
+            }
+        }
     }
-}
 
-impl Compiler for DeclareNd {
-    fn compile(&self, cxt: &mut Context) {
-        match &self.expr {
-            Some(e) => {
-                e.compile(cxt);
+    fn visit_declare(&mut self, n: &DeclareNd) {
+        let id = self.symbol(&n.var);
+        let ty = self.get_var_type(id);
+        if n.storage == StorageClass::Extern {
+            return;
+        }
+        if self.is_in_memory(id) {
+            // globals and static locals are initialized once, before running
+            match n.expr.as_ref().and_then(|e| e.try_to_str()) {
+                Some(s) => self.set_memory_str(id, s),
+                None => {
+                    let elem = match &ty {
+                        Type::Array(t, _) => t,
+                        _ => &ty,
+                    };
+                    for (i, e) in n.expr.iter().chain(n.list.iter()).enumerate() {
+                        self.init_memory(id, i, e, elem);
+                    }
+                }
+            }
+            return;
+        }
+        if let Type::Array(_, len) = &ty {
+            if !n.list.is_empty() {
+                for e in n.list.iter() {
+                    self.visit_expr(e);
+                }
+                self.add_zeros(len - n.list.len());
+                return;
             }
+        }
+        match &n.expr {
+            Some(e) => match (&ty, e.try_to_str()) {
+                (Type::Array(_, len), Some(s)) => {
+                    // copy the literal, pad the rest with zeros
+                    let addr = self.add_string(s);
+                    let sz = s.chars().count() + 1;
+                    self.add_code(Code::PushAddr(MemAddr::Direct(addr)));
+                    self.add_code(Code::Load(sz));
+                    self.add_zeros(len - sz);
+                }
+                _ => self.visit_expr(e),
+            },
             None => {
-                cxt.add_code(Code::PushValue(0));
+                self.add_zeros(self.get_type_size(&ty));
             }
         };
     }
-}
 
-impl Compiler for StmtNd {
-    fn compile(&self, cxt: &mut Context) {
-        match self {
-            StmtNd::Assign(n) => {
-                n.compile(cxt);
-            }
-            StmtNd::Declare(n) => n.compile(cxt),
+    fn visit_stmt(&mut self, n: &StmtNd) {
+        match n {
+            StmtNd::Assign(n) => self.visit_assign(n),
+            StmtNd::Declare(n) => self.visit_declare(n),
             StmtNd::Expr(n) => {
-                n.compile(cxt);
-                cxt.add_code(Code::Pop(1));
+                self.visit_expr(n);
+                let sz = self.get_type_size(&self.expr_type(n));
+                if sz > 0 {
+                    self.add_code(Code::Pop(sz));
+                }
             }
             StmtNd::Print(n) => {
-                cxt.push(n.get_id());
-                cxt.add_code(Code::Print);
-                cxt.add_code(Code::Pop(1));
+                self.push(self.symbol(n));
+                self.add_code(Code::Print);
+                self.add_code(Code::Pop(1));
             }
-            _ => {}
-        }
-    }
-}
-
-impl Compiler for IfNd {
-    fn compile(&self, cxt: &mut Context) {
-        cxt.enter(self.get_id());
-        self.expr.compile(cxt);
-        cxt.add_code(Code::CondJump(CodeAddr::NameEnd(self.get_id())));
-        self.item.compile(cxt);
-        cxt.exit(self.get_id());
-        if self.els.is_some() {
-            self.els.as_ref().unwrap().compile(cxt);
+            StmtNd::Empty => {}
         }
     }
-}
 
-impl Compiler for ElsNd {
-    fn compile(&self, cxt: &mut Context) {
-        match self {
-            ElsNd::If(n) => n.compile(cxt),
-            ElsNd::Item(n) => n.compile(cxt),
+    fn visit_if(&mut self, n: &IfNd) {
+        let scope = self.table().scope(n.id);
+        self.enter(scope);
+        self.visit_expr(&n.expr);
+        self.add_code(Code::CondJump(CodeAddr::NameEnd(scope)));
+        self.visit_item(&n.item);
+        self.exit(scope);
+        if let Some(els) = &n.els {
+            self.visit_els(els);
         }
     }
-}
 
-impl Compiler for WhileNd {
-    fn compile(&self, cxt: &mut Context) {
-        cxt.enter(self.get_id());
-        self.expr.compile(cxt);
-        cxt.add_code(Code::CondJump(CodeAddr::NameEnd(self.get_id())));
-        self.item.compile(cxt);
-        cxt.add_code(Code::Jump(CodeAddr::NameStart(self.get_id())));
-        cxt.exit(self.get_id());
+    fn visit_while(&mut self, n: &WhileNd) {
+        let scope = self.table().scope(n.id);
+        self.enter(scope);
+        self.visit_expr(&n.expr);
+        self.add_code(Code::CondJump(CodeAddr::NameEnd(scope)));
+        self.visit_item(&n.item);
+        self.add_code(Code::Jump(CodeAddr::NameStart(scope)));
+        self.exit(scope);
     }
-}
 
-impl Compiler for BreakNd {
-    fn compile(&self, cxt: &mut Context) {
-        cxt.add_code(Code::Pop(self.get_pop_off()));
-        cxt.add_code(Code::Jump(CodeAddr::NameEnd(self.get_id())));
+    fn visit_break(&mut self, n: &BreakNd) {
+        self.add_code(Code::Pop(self.table().pop_off(n.id)));
+        self.add_code(Code::Jump(CodeAddr::NameEnd(self.table().scope(n.id))));
     }
-}
 
-impl Compiler for ContinueNd {
-    fn compile(&self, cxt: &mut Context) {
-        cxt.add_code(Code::Pop(self.get_pop_off()));
-        cxt.add_code(Code::Jump(CodeAddr::NameStart(self.get_id())));
+    fn visit_continue(&mut self, n: &ContinueNd) {
+        self.add_code(Code::Pop(self.table().pop_off(n.id)));
+        self.add_code(Code::Jump(CodeAddr::NameStart(self.table().scope(n.id))));
     }
-}
 
-impl Compiler for ReturnNd {
-    fn compile(&self, cxt: &mut Context) {
-        match self.expr.as_ref() {
-            Some(n) => {
-                n.compile(cxt);
-                cxt.add_code(Code::Ret(self.get_sz()));
-            }
-            None => {
-                let sz = self.get_sz();
-                for _ in 0..sz {
-                    cxt.add_code(Code::PushValue(0));
-                }
-                cxt.add_code(Code::Ret(0));
-            }
+    fn visit_return(&mut self, n: &ReturnNd) {
+        let sz = self.table().ret_size(n.id);
+        match n.expr.as_ref() {
+            Some(e) => self.visit_expr(e),
+            None => self.add_zeros(sz),
         }
+        self.ret(sz);
     }
-}
 
-impl Compiler for ItemNd {
-    fn compile(&self, cxt: &mut Context) {
-        match self {
-            ItemNd::Block(n) => n.compile(cxt),
-            ItemNd::Stmt(n) => n.compile(cxt),
-            ItemNd::If(n) => n.compile(cxt),
-            ItemNd::While(n) => n.compile(cxt),
-            ItemNd::Break(n) => n.compile(cxt),
-            ItemNd::Continue(n) => n.compile(cxt),
-            ItemNd::Return(n) => n.compile(cxt),
+    fn visit_block(&mut self, n: &BlockNd) {
+        let scope = self.table().scope(n.id);
+        self.enter(scope);
+        for it in n.items.iter() {
+            self.visit_item(it);
         }
+        let sz = self.get_scope_size(scope);
+        self.add_code(Code::Pop(sz));
+        self.exit(scope);
     }
-}
 
-impl Compiler for BlockNd {
-    fn compile(&self, cxt: &mut Context) {
-        cxt.enter(self.get_id());
-        for it in self.items.iter() {
-            it.compile(cxt);
+    fn visit_func(&mut self, n: &FuncNd) {
+        if let Some(b) = &n.block {
+            let id = self.symbol(&n.var);
+            self.enter_func(id, &n.var.name);
+            self.visit_block(b);
+            self.exit_func();
         }
-        let sz = cxt.get_scope_size(self.get_id());
-        cxt.add_code(Code::Pop(sz));
-        cxt.exit(self.get_id());
     }
-}
 
-impl Compiler for FuncNd {
-    fn compile(&self, cxt: &mut Context) {
-        if self.is_impl() {
-            let id = self.var.get_id();
-            cxt.enter_func(id);
-            self.block.as_ref().unwrap().compile(cxt);
-            cxt.exit_func();
-        }
+    fn visit_func_call(&mut self, n: &FuncCallNd) {
+        let id = self.symbol(&n.var);
+        compile_args(self, &self.get_func_type(id), &n.params);
+        self.call(id);
     }
-}
 
-impl Compiler for FuncCallNd {
-    fn compile(&self, cxt: &mut Context) {
-        for p in self.params.iter() {
-            p.compile(cxt);
-        }
-        let id = self.var.get_id();
-        cxt.call(id);
-    }
-}
+    // types only, nothing to emit
+    fn visit_struct(&mut self, _n: &StructNd) {}
 
-impl Compiler for GItemNd {
-    fn compile(&self, cxt: &mut Context) {
-        match self {
-            GItemNd::Func(n) => n.compile(cxt),
-            GItemNd::Declare(n) => {
-                // add memory
-                if n.ty == Type::Int {
-                    let v = n.try_retrieve_const().unwrap();
-                    cxt.add_memory(v);
-                }
-            }
-        }
-    }
+    fn visit_enum(&mut self, _n: &EnumNd) {}
+
+    fn visit_typedef(&mut self, _n: &TypedefNd) {}
 }
 
-impl Compiler for RootNd {
-    fn compile(&self, cxt: &mut Context) {
-        for it in self.items.iter() {
-            it.compile(cxt);
+// variadic args go first with their cell count, then the fixed params
+fn compile_args(cxt: &mut Context, fty: &Type, params: &[ExprNd]) {
+    let fixed = match fty {
+        Type::Func(v, true) => v.len() - 1,
+        _ => params.len(),
+    };
+    if let Type::Func(_, true) = fty {
+        let mut cells = 0;
+        for p in params[fixed..].iter() {
+            cxt.visit_expr(p);
+            cells += cxt.get_type_size(&cxt.expr_type(p));
         }
+        cxt.add_code(Code::PushValue(cells as i32));
+    }
+    for p in params[..fixed].iter() {
+        cxt.visit_expr(p);
     }
 }
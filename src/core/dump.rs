@@ -0,0 +1,69 @@
+use super::analyzer::analyze_root;
+use super::compiler::compile_source;
+use super::lexer::lex_source;
+use super::parser::parse_source;
+use super::types::{Error, RootNd, SemanticError};
+use serde::Serialize;
+
+// JSON of each pipeline stage, for tools that inspect or cache them.
+// Semantic tables are keyed by node ids, dump it with the tree it was
+// analyzed from to load both back
+pub fn to_json<T: Serialize>(v: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(v)
+}
+
+// stage results have string or integer keys only
+fn dump<T: Serialize>(v: &T) -> String {
+    to_json(v).expect("stage result is plain data")
+}
+
+pub fn tokens_json(code: &str) -> Result<String, Error> {
+    Ok(dump(&lex_source(code)?))
+}
+
+pub fn ast_json(code: &str) -> Result<String, Error> {
+    Ok(dump(&parse_source::<RootNd>(code)?))
+}
+
+pub fn semantic_json(code: &str) -> Result<String, Error> {
+    let nd = parse_source::<RootNd>(code)?;
+    let info = analyze_root(&nd).map_err(|kind| SemanticError { kind })?;
+    Ok(dump(&info))
+}
+
+pub fn program_json(code: &str) -> Result<String, Error> {
+    Ok(dump(&compile_source(code)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ast_json, program_json, to_json, tokens_json};
+    use crate::core::analyzer::{analyze, Semantic};
+    use crate::core::compiler::{compile_root, Program};
+    use crate::core::lexer::lexer;
+    use crate::core::printer::{format, Print};
+    use crate::core::types::{RootNd, Token};
+    use crate::core::VM;
+    use crate::utils::load_code_from_file;
+
+    #[test]
+    fn test_dump_round_trip() {
+        let code = load_code_from_file("test_cfiles/vm/func_ptr.c");
+        let expected = vec![1234, 4321, 55, 15, 5, 1, 1, 2];
+        let tks: Vec<Token> = serde_json::from_str(&tokens_json(&code).unwrap()).unwrap();
+        assert_eq!(tks, lexer(&code).unwrap());
+
+        let nd: RootNd = serde_json::from_str(&ast_json(&code).unwrap()).unwrap();
+        assert_eq!(nd.print(), format(&code).unwrap());
+
+        let prog: Program = serde_json::from_str(&program_json(&code).unwrap()).unwrap();
+        assert_eq!(VM::new(1000, prog).execute().unwrap(), expected);
+
+        // a loaded tree compiles against the loaded tables it was analyzed with
+        let (nd, info) = analyze(&code).unwrap();
+        let nd: RootNd = serde_json::from_str(&to_json(&nd).unwrap()).unwrap();
+        let info: Semantic = serde_json::from_str(&to_json(&info).unwrap()).unwrap();
+        let prog = compile_root(&nd, info).unwrap();
+        assert_eq!(VM::new(1000, prog).execute().unwrap(), expected);
+    }
+}
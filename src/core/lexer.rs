@@ -1,4 +1,71 @@
-use super::types::{get_token_from_char, get_token_from_word, ErrKind, Token};
+use super::types::{get_token_from_char, get_token_from_word, ErrKind, LexError, Token, Value};
+use crate::utils::preprocess_code;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// the char after a backslash, octal and hex escapes take more digits
+fn read_escape(it: &mut Peekable<Chars>) -> Result<u8, ErrKind> {
+    let c = it.next().ok_or(ErrKind::LexErr)?;
+    let b = match c {
+        'n' => b'\n',
+        't' => b'\t',
+        'r' => b'\r',
+        'a' => 0x07,
+        'b' => 0x08,
+        'f' => 0x0c,
+        'v' => 0x0b,
+        '\\' | '\'' | '"' | '?' => c as u8,
+        '0'..='7' => {
+            let mut n = c.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match it.peek().and_then(|d| d.to_digit(8)) {
+                    Some(d) => {
+                        n = n * 8 + d;
+                        it.next();
+                    }
+                    None => break,
+                }
+            }
+            if n > 0xff {
+                return Err(ErrKind::LexErr);
+            }
+            n as u8
+        }
+        'x' => {
+            let mut n = 0u32;
+            let mut digits = 0;
+            while let Some(d) = it.peek().and_then(|d| d.to_digit(16)) {
+                n = n * 16 + d;
+                digits += 1;
+                it.next();
+                if n > 0xff {
+                    return Err(ErrKind::LexErr);
+                }
+            }
+            if digits == 0 {
+                return Err(ErrKind::LexErr);
+            }
+            n as u8
+        }
+        _ => return Err(ErrKind::LexErr),
+    };
+    Ok(b)
+}
+
+// bytes up to the closing quote, the opening one is already eaten. Each char
+// stands for one byte (escapes may give 128..=255), stored back as that byte
+fn read_quoted(it: &mut Peekable<Chars>, quote: char) -> Result<String, ErrKind> {
+    let mut s = String::new();
+    loop {
+        match it.next() {
+            Some(c) if c == quote => return Ok(s),
+            Some('\\') => s.push(read_escape(it)? as char),
+            Some('\n') | None => return Err(ErrKind::LexErr),
+            Some(c) if c.is_ascii() => s.push(c),
+            Some(_) => return Err(ErrKind::LexErr),
+        }
+    }
+}
 
 #[allow(dead_code)]
 pub fn lexer(code: &str) -> Result<Vec<Token>, ErrKind> {
@@ -17,9 +84,23 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ErrKind> {
             }
         };
     }
-    for c in code.chars() {
+    // source may come from memory with its // comments still in
+    let code = preprocess_code(code.to_owned());
+    let mut it = code.chars().peekable();
+    while let Some(c) = it.next() {
         if c.is_ascii_whitespace() {
             try_push_word!();
+        } else if c == '"' {
+            try_push_word!();
+            tokens.push(Token::Str(read_quoted(&mut it, '"')?));
+        } else if c == '\'' {
+            try_push_word!();
+            let s = read_quoted(&mut it, '\'')?;
+            let mut cs = s.chars();
+            match (cs.next(), cs.next()) {
+                (Some(ch), None) => tokens.push(Token::Value(Value::Int(ch as u8 as i8 as i32))),
+                _ => return Err(ErrKind::LexErr),
+            }
         } else if let Some(t) = get_token_from_char(c) {
             try_push_word!();
             tokens.push(t);
@@ -31,6 +112,28 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ErrKind> {
     Ok(tokens)
 }
 
+// no token spans lines, so each line lexes on its own
+pub fn lex_source(code: &str) -> Result<Vec<Token>, LexError> {
+    lexer(code).map_err(|_| {
+        let line = code.split('\n').position(|l| lexer(l).is_err());
+        LexError {
+            line: line.unwrap_or(0) + 1,
+        }
+    })
+}
+
+// line of the token at `pos`, 1-based
+pub fn token_line(code: &str, pos: usize) -> usize {
+    let mut n = 0;
+    for (i, l) in code.split('\n').enumerate() {
+        n += lexer(l).map(|v| v.len()).unwrap_or(0);
+        if n > pos {
+            return i + 1;
+        }
+    }
+    code.split('\n').count()
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::types::Type;
@@ -50,8 +153,20 @@ mod tests {
                 assert_eq!(tks[5], Token::RBrace);
             }
             Err(_) => {
-                assert!(false, "Not Ok");
+                panic!("Not Ok");
             }
         }
     }
+
+    #[test]
+    fn test_lexer_literal() {
+        let code = r#"char *s = "a\tb\"\x41\101\0"; char c = '\n';"#;
+        let tks = lexer(code).unwrap();
+        assert_eq!(tks[0], Token::Type(Type::Char));
+        assert_eq!(tks[4], Token::Str("a\tb\"AA\0".to_owned()));
+        assert_eq!(tks[9], Token::Value(Value::Int(10)));
+        assert_eq!(lexer("\"abc"), Err(ErrKind::LexErr));
+        assert_eq!(lexer("'ab'"), Err(ErrKind::LexErr));
+        assert_eq!(lexer("\"\\q\""), Err(ErrKind::LexErr));
+    }
 }
@@ -1,34 +1,50 @@
 mod imp;
 
-use super::types::{SeqPack, Sequence};
-pub use imp::*;
+use super::lexer::{lex_source, token_line};
+use super::types::{Error, ParseError, SeqPack, Sequence, Type};
+use std::collections::HashMap;
 
 pub trait Parser: Sized {
     fn parse(seq: Sequence) -> SeqPack<Self>;
 }
 
+#[allow(dead_code)]
 pub fn parse<T: Parser>(code: &str) -> Result<T, String> {
-    let lex_res = super::lexer::lexer(code);
-    if let Err(e) = lex_res {
-        return Err(format!("Lex error: {:?}", e));
-    }
-    let seq = Sequence::new(lex_res.unwrap());
-    let parse_res = T::parse(seq);
-    if parse_res.is_none() {
-        Err("Parse error".to_owned())
-    } else {
-        let (seq, t) = parse_res.unwrap();
-        if !seq.empty() {
-            Err(format!("Not eat all seq, remains {:?}", seq.len()))
-        } else {
+    parse_source(code).map_err(|e| e.to_string())
+}
+
+pub fn parse_source<T: Parser>(code: &str) -> Result<T, Error> {
+    parse_with_typedefs(code, &mut HashMap::new())
+}
+
+// typedefs of earlier code are known, the code's own are added on success
+pub fn parse_with_typedefs<T: Parser>(
+    code: &str,
+    typedefs: &mut HashMap<String, Type>,
+) -> Result<T, Error> {
+    let tks = lex_source(code)?;
+    let seq = Sequence::with_typedefs(tks.clone(), typedefs.clone());
+    match T::parse(seq.clone()) {
+        Some((s, t)) if s.empty() => {
+            *typedefs = seq.typedefs();
             Ok(t)
         }
+        _ => {
+            let pos = seq.furthest();
+            let line = token_line(code, pos);
+            Err(Error::Parse(ParseError {
+                line,
+                near: tks.get(pos).cloned(),
+            }))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::types::nodes::*;
+    use super::super::types::visit::{walk_func_call, Visitor, VisitorMut};
+    use super::super::types::Type;
     use super::parse;
 
     #[test]
@@ -116,4 +132,119 @@ mod tests {
         let parse_res = parse::<IfNd>(code);
         parse_res.unwrap();
     }
+
+    #[test]
+    fn test_parse_pointer() {
+        let code = "int *swap(int *a, int b[], int **);";
+        let parse_res = parse::<FuncNd>(code);
+        parse_res.unwrap();
+        let code = "int m[2][3]";
+        let parse_res = parse::<DeclareNd>(code);
+        parse_res.unwrap();
+        let code = "*p = a[1][2] * *q + -*&b;";
+        let parse_res = parse::<StmtNd>(code);
+        parse_res.unwrap();
+        let code = "int a[]";
+        let parse_res = parse::<DeclareNd>(code);
+        assert!(parse_res.is_err());
+    }
+
+    #[test]
+    fn test_parse_struct() {
+        let code = "struct Node { int v; struct Node *next; int tag[2]; };";
+        let parse_res = parse::<StructNd>(code);
+        parse_res.unwrap();
+        let code = "p->next->v = a.b[1].c;";
+        let parse_res = parse::<StmtNd>(code);
+        parse_res.unwrap();
+        let code = "struct Node { int v = 1; };";
+        let parse_res = parse::<StructNd>(code);
+        assert!(parse_res.is_err());
+    }
+
+    #[test]
+    fn test_parse_typedef() {
+        let code = "enum Color { RED, GREEN = 5, BLUE, };";
+        let parse_res = parse::<EnumNd>(code);
+        parse_res.unwrap();
+        let code = "typedef struct { int x; } P; typedef P *PP; PP f(P a, PP); P *g;";
+        let parse_res = parse::<RootNd>(code);
+        parse_res.unwrap();
+        let code = "P x;";
+        let parse_res = parse::<RootNd>(code);
+        assert!(parse_res.is_err());
+    }
+
+    #[test]
+    fn test_parse_sizeof() {
+        let code = "int a[sizeof(int) * 2] = sizeof a + sizeof(struct P *)";
+        let nd = parse::<DeclareNd>(code).unwrap();
+        assert_eq!(nd.ty, Type::Array(Box::new(Type::Int), 0));
+        assert!(nd.bounds[0].is_some());
+        let code = "int a[2 * 3]";
+        let nd = parse::<DeclareNd>(code).unwrap();
+        assert_eq!(nd.ty, Type::Array(Box::new(Type::Int), 6));
+        let code = "int a[0]";
+        assert!(parse::<DeclareNd>(code).is_err());
+    }
+
+    #[test]
+    fn test_parse_func_ptr() {
+        let code = "int (*ops[2])(int, char *)";
+        let nd = parse::<DeclareNd>(code).unwrap();
+        let f = Type::Func(
+            vec![Type::Int, Type::Ptr(Box::new(Type::Char)), Type::Int],
+            false,
+        );
+        let ty = Type::Array(Box::new(Type::Ptr(Box::new(f))), 2);
+        assert_eq!(nd.ty, ty);
+        let code = "(*fp)(1, 2) + ops[0](3)";
+        parse::<ExprNd>(code).unwrap();
+    }
+
+    #[test]
+    fn test_parse_variadic() {
+        let code = "int f(int n, ...);";
+        assert!(parse::<FuncNd>(code).unwrap().variadic);
+        let code = "int f(...);";
+        assert!(parse::<FuncNd>(code).is_err());
+        let code = "int f(int n, ..., int m);";
+        assert!(parse::<FuncNd>(code).is_err());
+    }
+
+    #[test]
+    fn test_visit() {
+        struct Calls(Vec<String>);
+        impl Visitor for Calls {
+            fn visit_func_call(&mut self, n: &FuncCallNd) {
+                self.0.push(n.var.name.clone());
+                walk_func_call(self, n);
+            }
+        }
+        struct Rename;
+        impl VisitorMut for Rename {
+            fn visit_var_mut(&mut self, n: &mut VarNd) {
+                if n.name == "gcd" {
+                    n.name = "euclid".to_string();
+                }
+            }
+        }
+
+        let code = "int gcd(int a, int b) { if (b == 0) { return a; } \
+                    return gcd(b, a % b); } \
+                    int main() { int p = gcd(gcd(8, 12), 90); p; }";
+        let mut nd = parse::<RootNd>(code).unwrap();
+        let mut calls = Calls(vec![]);
+        calls.visit_root(&nd);
+        assert_eq!(calls.0, vec!["gcd", "gcd", "gcd"]);
+
+        Rename.visit_root_mut(&mut nd);
+        let mut calls = Calls(vec![]);
+        calls.visit_root(&nd);
+        assert_eq!(calls.0, vec!["euclid", "euclid", "euclid"]);
+        match &nd.items[0] {
+            GItemNd::Func(f) => assert_eq!(f.var.name, "euclid"),
+            _ => panic!(),
+        }
+    }
 }
@@ -1,12 +1,136 @@
 use super::Parser;
 use crate::core::types::nodes::*;
-use crate::core::types::{get_calc_stack, SeqPack, Sequence, Token};
+use crate::core::types::{
+    eat_call_args, get_calc_stack, NodeTable, SeqPack, Sequence, StorageClass, Token, Type,
+};
+use std::collections::HashMap;
+
+// [N][M]..., an empty bound is kept as 0, so is a bound that is not a literal
+// and the analyzer resolves it from its expression
+fn eat_dims(seq: Sequence, ty: Type) -> SeqPack<(Type, Vec<Option<ExprNd>>)> {
+    let mut dims = Vec::new();
+    let mut bounds = Vec::new();
+    let mut mseq = seq;
+    while let Some((seq, _)) = mseq.eat(Token::LBracket) {
+        if let Some((seq, _)) = seq.eat(Token::RBracket) {
+            dims.push(0);
+            bounds.push(None);
+            mseq = seq;
+        } else {
+            let (seq, ex) = ExprNd::parse(seq)?;
+            let (seq, _) = seq.eat(Token::RBracket)?;
+            match ex.try_retrieve_const(&HashMap::new(), &NodeTable::new()) {
+                Some(n) if n > 0 => {
+                    dims.push(n as usize);
+                    bounds.push(None);
+                }
+                Some(_) => return None,
+                None => {
+                    dims.push(0);
+                    bounds.push(Some(ex));
+                }
+            }
+            mseq = seq;
+        }
+    }
+    let mut ty = ty;
+    for n in dims.into_iter().rev() {
+        ty = Type::Array(Box::new(ty), n);
+    }
+    Some((mseq, (ty, bounds)))
+}
+
+fn has_empty_dim(ty: &Type, bounds: &[Option<ExprNd>]) -> bool {
+    match (ty, bounds.split_first()) {
+        (Type::Array(t, n), Some((b, rest))) => *n == 0 && b.is_none() || has_empty_dim(t, rest),
+        (Type::Array(t, n), None) => *n == 0 || has_empty_dim(t, &[]),
+        _ => false,
+    }
+}
+
+// {a, b, c}, at least one item
+fn eat_init_list(seq: Sequence) -> SeqPack<Vec<ExprNd>> {
+    let (seq, _) = seq.eat(Token::LBrace)?;
+    let (mut mseq, ex) = ExprNd::parse(seq)?;
+    let mut list = vec![ex];
+    while let Some((seq, _)) = mseq.eat(Token::Comma) {
+        let (seq, ex) = ExprNd::parse(seq)?;
+        list.push(ex);
+        mseq = seq;
+    }
+    let (seq, _) = mseq.eat(Token::RBrace)?;
+    Some((seq, list))
+}
+
+fn eat_storage(seq: Sequence) -> (Sequence, StorageClass) {
+    match seq.swtich_eat(&[Token::Static, Token::Extern]) {
+        Some((seq, Token::Static)) => (seq, StorageClass::Static),
+        Some((seq, _)) => (seq, StorageClass::Extern),
+        None => (seq, StorageClass::Auto),
+    }
+}
+
+// put `elem` as the element of a (maybe nested) array type
+fn with_elem(ty: Type, elem: Type) -> Type {
+    match ty {
+        Type::Array(t, n) => Type::Array(Box::new(with_elem(*t, elem)), n),
+        _ => elem,
+    }
+}
+
+// (*name[N]...)(params), a function pointer or an array of them
+fn eat_func_ptr_declarator(
+    seq: Sequence,
+    ret: Type,
+) -> SeqPack<(Type, VarNd, Vec<Option<ExprNd>>)> {
+    let (seq, _) = seq.eats(&[Token::LParen, Token::Multiply])?;
+    let (seq, v) = VarNd::parse(seq)?;
+    let (seq, (ty, bounds)) = eat_dims(seq, Type::Void)?;
+    let (seq, _) = seq.eat(Token::RParen)?;
+    let (seq, fty) = seq.eat_func_params(ret)?;
+    Some((seq, (with_elem(ty, Type::Ptr(Box::new(fty))), v, bounds)))
+}
+
+// array params are adjusted to pointers
+fn eat_param(seq: Sequence) -> SeqPack<(Type, Option<VarNd>)> {
+    let (seq, ty) = seq.eat_type()?;
+    let (seq, ty) = seq.eat_pointers(ty);
+    if let Some((seq, (ty, name))) = seq.eat_func_ptr(ty.clone()) {
+        return Some((seq, (ty, name.map(VarNd::new))));
+    }
+    let (seq, var) = match VarNd::parse(seq.clone()) {
+        Some((seq, vn)) => (seq, Some(vn)),
+        None => (seq, None),
+    };
+    let (seq, (ty, bounds)) = eat_dims(seq, ty)?;
+    // only the adjusted outer bound may be an expression
+    let ty = match ty {
+        Type::Array(t, _) => Type::Ptr(t),
+        _ => ty,
+    };
+    if bounds.iter().skip(1).any(|b| b.is_some()) || has_empty_dim(&ty, &[]) {
+        return None;
+    }
+    Some((seq, (ty, var)))
+}
 
 impl Parser for FactorNd {
     fn parse(seq: Sequence) -> SeqPack<Self> {
         if let Some((seq, n)) = seq.clone().eat_value() {
             return Some((seq, FactorNd::Value(n)));
         }
+        if let Some((seq, n)) = seq.clone().eat_str() {
+            // adjacent literals are joined
+            let (mut mseq, mut s) = (seq, n);
+            while let Some((seq, n)) = mseq.eat_str() {
+                s.push_str(&n);
+                mseq = seq;
+            }
+            return Some((mseq, FactorNd::Str(s)));
+        }
+        if let Some((seq, n)) = VaNd::parse(seq.clone()) {
+            return Some((seq, FactorNd::Va(n)));
+        }
         if let Some((seq, n)) = FuncCallNd::parse(seq.clone()) {
             return Some((seq, FactorNd::Func(n)));
         }
@@ -33,25 +157,56 @@ impl Parser for VarNd {
 
 impl Parser for AssignNd {
     fn parse(seq: Sequence) -> SeqPack<Self> {
-        let (seq, v) = VarNd::parse(seq)?;
+        let (seq, lhs) = ExprNd::parse(seq)?;
         let (seq, _) = seq.eat(Token::Eq)?;
         let (seq, ex) = ExprNd::parse(seq)?;
-        Some((seq, AssignNd::new(v, ex)))
+        Some((seq, AssignNd::new(lhs, ex)))
     }
 }
 
 impl Parser for DeclareNd {
     fn parse(seq: Sequence) -> SeqPack<Self> {
+        let (seq, storage) = eat_storage(seq);
         let (seq, ty) = seq.eat_type()?;
-        let (seq, v) = VarNd::parse(seq)?;
-        let sp = seq.clone().eat(Token::Eq);
-        if sp.is_none() {
-            Some((seq, DeclareNd::new(ty, v, None)))
-        } else {
-            let (seq, _) = sp?;
-            let (seq, ex) = ExprNd::parse(seq)?;
-            Some((seq, DeclareNd::new(ty, v, Some(ex))))
+        let (seq, ty) = seq.eat_pointers(ty);
+        let (seq, (ty, v, bounds)) = match eat_func_ptr_declarator(seq.clone(), ty.clone()) {
+            Some(res) => res,
+            None => {
+                let (seq, v) = VarNd::parse(seq)?;
+                let (seq, (ty, bounds)) = eat_dims(seq, ty)?;
+                (seq, (ty, v, bounds))
+            }
+        };
+        let (seq, ex, list) = match seq.clone().eat(Token::Eq) {
+            Some((seq, _)) if seq.get(0) == Some(Token::LBrace) => {
+                let (seq, list) = eat_init_list(seq)?;
+                (seq, None, list)
+            }
+            Some((seq, _)) => {
+                let (seq, ex) = ExprNd::parse(seq)?;
+                (seq, Some(ex), Vec::new())
+            }
+            None => (seq, None, Vec::new()),
+        };
+        // char s[] = "..." takes the size of the literal, a[] = {...} its items
+        let ty = match (ty, ex.as_ref().and_then(|e| e.try_to_str())) {
+            (Type::Array(t, 0), Some(s))
+                if *t.unqualified() == Type::Char && bounds[0].is_none() =>
+            {
+                Type::Array(t, s.chars().count() + 1)
+            }
+            (Type::Array(t, 0), None) if !list.is_empty() && bounds[0].is_none() => {
+                Type::Array(t, list.len())
+            }
+            (ty, _) => ty,
+        };
+        let init = ex.is_some() || !list.is_empty();
+        if has_empty_dim(&ty, &bounds) || storage == StorageClass::Extern && init {
+            return None;
         }
+        let mut nd = DeclareNd::new(storage, ty, bounds, v, ex);
+        nd.list = list;
+        Some((seq, nd))
     }
 }
 
@@ -198,29 +353,33 @@ impl Parser for BlockNd {
 impl Parser for FuncNd {
     fn parse(seq: Sequence) -> SeqPack<Self> {
         let (seq, ret_ty) = seq.eat_type()?;
+        let (seq, ret_ty) = seq.eat_pointers(ret_ty);
         let (seq, func_var) = VarNd::parse(seq)?;
         let mut params = Vec::new();
-        let mut first_param = true;
+        let mut variadic = false;
         let (mut mseq, _) = seq.eat(Token::LParen)?;
         while mseq.get(0) != Some(Token::RParen) {
-            if !first_param {
-                let (seq, _) = mseq.eat(Token::Comma)?;
-                mseq = seq;
+            if variadic {
+                return None;
             }
-            first_param = false;
-            let (seq, ty) = mseq.eat_type()?;
-            if let Some((seq, vn)) = VarNd::parse(seq.clone()) {
-                params.push((ty, Some(vn)));
-                mseq = seq;
-            } else {
-                params.push((ty, None));
+            if !params.is_empty() {
+                let (seq, _) = mseq.eat(Token::Comma)?;
                 mseq = seq;
+                // at least one named param before ...
+                if let Some((seq, _)) = mseq.eat_ellipsis() {
+                    variadic = true;
+                    mseq = seq;
+                    continue;
+                }
             }
+            let (seq, param) = eat_param(mseq)?;
+            params.push(param);
+            mseq = seq;
         }
         mseq = mseq.advance(1);
         if let Some((seq, block)) = BlockNd::parse(mseq.clone()) {
             mseq = seq;
-            let nd = FuncNd::new(ret_ty, func_var, params, Some(block));
+            let nd = FuncNd::new(ret_ty, func_var, params, variadic, Some(block));
             if nd.check() {
                 Some((mseq, nd))
             } else {
@@ -228,7 +387,7 @@ impl Parser for FuncNd {
             }
         } else {
             let (mseq, _) = mseq.eat(Token::Semicolon)?;
-            let nd = FuncNd::new(ret_ty, func_var, params, None);
+            let nd = FuncNd::new(ret_ty, func_var, params, variadic, None);
             Some((mseq, nd))
         }
     }
@@ -237,25 +396,142 @@ impl Parser for FuncNd {
 impl Parser for FuncCallNd {
     fn parse(seq: Sequence) -> SeqPack<Self> {
         let (seq, func_var) = VarNd::parse(seq)?;
-        let mut params = Vec::new();
-        let mut first_param = true;
-        let (mut mseq, _) = seq.eat(Token::LParen)?;
-        while mseq.get(0) != Some(Token::RParen) {
-            if !first_param {
+        let (seq, params) = eat_call_args(seq)?;
+        Some((seq, FuncCallNd::new(func_var, params)))
+    }
+}
+
+impl Parser for VaNd {
+    fn parse(seq: Sequence) -> SeqPack<Self> {
+        match seq.swtich_eat(&[Token::VaStart, Token::VaArg, Token::VaEnd])? {
+            (seq, Token::VaStart) => {
+                let (seq, _) = seq.eat(Token::LParen)?;
+                let (seq, ap) = VarNd::parse(seq)?;
+                let (seq, _) = seq.eat(Token::Comma)?;
+                let (seq, last) = VarNd::parse(seq)?;
+                let (seq, _) = seq.eat(Token::RParen)?;
+                Some((seq, VaNd::Start(ap, last)))
+            }
+            (seq, Token::VaArg) => {
+                let (seq, _) = seq.eat(Token::LParen)?;
+                let (seq, ap) = VarNd::parse(seq)?;
+                let (seq, _) = seq.eat(Token::Comma)?;
+                let (seq, ty) = seq.eat_type_name()?;
+                let (seq, _) = seq.eat(Token::RParen)?;
+                Some((seq, VaNd::Arg(ap, ty)))
+            }
+            (seq, _) => {
+                let (seq, ap) = seq.eat_fn(&[Token::LParen], VarNd::parse, &[Token::RParen])?;
+                Some((seq, VaNd::End(ap)))
+            }
+        }
+    }
+}
+
+// { type name; ... }
+fn eat_struct_body(seq: Sequence, name: String) -> SeqPack<StructNd> {
+    let mut fields = Vec::new();
+    let (mut mseq, _) = seq.eat(Token::LBrace)?;
+    while mseq.get(0) != Some(Token::RBrace) {
+        let (seq, n) = DeclareNd::parse(mseq)?;
+        if n.expr.is_some() || n.storage != StorageClass::Auto {
+            return None;
+        }
+        let (seq, _) = seq.eat(Token::Semicolon)?;
+        fields.push(n);
+        mseq = seq;
+    }
+    Some((mseq.advance(1), StructNd::new(name, fields)))
+}
+
+impl Parser for StructNd {
+    fn parse(seq: Sequence) -> SeqPack<Self> {
+        let (seq, _) = seq.eat(Token::Struct)?;
+        let (seq, name) = seq.eat_name()?;
+        let (seq, nd) = eat_struct_body(seq, name)?;
+        let (seq, _) = seq.eat(Token::Semicolon)?;
+        Some((seq, nd))
+    }
+}
+
+impl Parser for EnumNd {
+    fn parse(seq: Sequence) -> SeqPack<Self> {
+        let (seq, _) = seq.eat(Token::Enum)?;
+        let (seq, name) = match seq.eat_name() {
+            Some((seq, name)) => (seq, Some(name)),
+            None => (seq, None),
+        };
+        let mut items = Vec::new();
+        let (mut mseq, _) = seq.eat(Token::LBrace)?;
+        while mseq.get(0) != Some(Token::RBrace) {
+            if !items.is_empty() {
                 let (seq, _) = mseq.eat(Token::Comma)?;
                 mseq = seq;
+                if mseq.get(0) == Some(Token::RBrace) {
+                    break;
+                }
             }
-            first_param = false;
-            let (seq, ex) = ExprNd::parse(mseq)?;
-            params.push(ex);
-            mseq = seq;
+            let (seq, v) = VarNd::parse(mseq)?;
+            if let Some((seq, _)) = seq.eat(Token::Eq) {
+                let (seq, ex) = ExprNd::parse(seq)?;
+                items.push((v, Some(ex)));
+                mseq = seq;
+            } else {
+                items.push((v, None));
+                mseq = seq;
+            }
+        }
+        let (mseq, _) = mseq.advance(1).eat(Token::Semicolon)?;
+        Some((mseq, EnumNd::new(name, items)))
+    }
+}
+
+impl Parser for TypedefNd {
+    fn parse(seq: Sequence) -> SeqPack<Self> {
+        let (seq, _) = seq.eat(Token::Typedef)?;
+        let has_body = seq.get(0) == Some(Token::Struct)
+            && (seq.get(1) == Some(Token::LBrace) || seq.get(2) == Some(Token::LBrace));
+        let (seq, ty, def) = if has_body {
+            let (seq, tag) = match seq.advance(1).eat_name() {
+                Some((seq, tag)) => (seq, Some(tag)),
+                None => (seq.advance(1), None),
+            };
+            let (seq, mut nd) = eat_struct_body(seq, String::new())?;
+            nd.name = match tag {
+                Some(tag) => tag,
+                None => StructNd::anonymous_tag(&seq.eat_pointers(Type::Void).0.eat_name()?.1),
+            };
+            (seq, Type::Struct(nd.name.clone()), Some(nd))
+        } else {
+            let (seq, ty) = seq.eat_type()?;
+            (seq, ty, None)
+        };
+        let (seq, ty) = seq.eat_pointers(ty);
+        let (seq, (ty, name)) = match seq.eat_func_ptr(ty.clone()) {
+            Some((seq, (ty, Some(name)))) => (seq, (ty, name)),
+            Some(_) => return None,
+            None => seq.eat_name().map(|(seq, name)| (seq, (ty, name)))?,
+        };
+        let (seq, (ty, bounds)) = eat_dims(seq, ty)?;
+        if bounds.iter().any(|b| b.is_some()) || has_empty_dim(&ty, &[]) {
+            return None;
         }
-        Some((mseq.advance(1), FuncCallNd::new(func_var, params)))
+        let (seq, _) = seq.eat(Token::Semicolon)?;
+        Some((seq, TypedefNd::new(ty, name, def)))
     }
 }
 
 impl Parser for GItemNd {
     fn parse(seq: Sequence) -> SeqPack<Self> {
+        if let Some((seq, n)) = StructNd::parse(seq.clone()) {
+            return Some((seq, GItemNd::Struct(n)));
+        }
+        if let Some((seq, n)) = EnumNd::parse(seq.clone()) {
+            return Some((seq, GItemNd::Enum(n)));
+        }
+        if let Some((seq, n)) = TypedefNd::parse(seq.clone()) {
+            return Some((seq, GItemNd::Typedef(n)));
+        }
         if let Some((seq, n)) = FuncNd::parse(seq.clone()) {
             return Some((seq, GItemNd::Func(n)));
         }
@@ -273,6 +549,9 @@ impl Parser for RootNd {
         let mut mseq = seq;
         while !mseq.empty() {
             let (seq, n) = GItemNd::parse(mseq)?;
+            if let GItemNd::Typedef(t) = &n {
+                seq.add_typedef(&t.name, t.ty.clone());
+            }
             items.push(n);
             mseq = seq;
         }
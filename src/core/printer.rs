@@ -0,0 +1,657 @@
+use super::parser::parse_source;
+use super::types::nodes::*;
+use super::types::visit::Visitor;
+use super::types::{
+    get_op_level, get_op_param_num, CalcItem, Error, Op, StorageClass, Type, Value,
+};
+
+const INDENT: &str = "    ";
+const POSTFIX_LEVEL: u8 = 1;
+const UNARY_LEVEL: u8 = 2;
+
+// Turns a tree back into C source, comments are dropped by the lexer so
+// they can't be kept, typedef and enum names are printed as what they resolve to
+pub trait Print {
+    fn print(&self) -> String;
+}
+
+pub fn format(code: &str) -> Result<String, Error> {
+    let nd = parse_source::<RootNd>(code)?;
+    Ok(nd.print())
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+}
+
+macro_rules! impl_print {
+    ($($nd:ty => $visit:ident),* $(,)?) => {
+        $(impl Print for $nd {
+            fn print(&self) -> String {
+                let mut p = Printer::default();
+                p.$visit(self);
+                p.out
+            }
+        })*
+    };
+}
+
+impl_print!(
+    RootNd => visit_root,
+    GItemNd => visit_gitem,
+    FuncNd => visit_func,
+    StructNd => visit_struct,
+    EnumNd => visit_enum,
+    TypedefNd => visit_typedef,
+    DeclareNd => visit_declare,
+    BlockNd => visit_block,
+    ItemNd => visit_item,
+    StmtNd => visit_stmt,
+    AssignNd => visit_assign,
+    IfNd => visit_if,
+    WhileNd => visit_while,
+    ReturnNd => visit_return,
+    BreakNd => visit_break,
+    ContinueNd => visit_continue,
+    ExprNd => visit_expr,
+    FactorNd => visit_factor,
+    VarNd => visit_var,
+    FuncCallNd => visit_func_call,
+    IndirectCallNd => visit_indirect_call,
+    SizeofNd => visit_sizeof,
+    VaNd => visit_va,
+);
+
+impl Print for Type {
+    fn print(&self) -> String {
+        declarator(self, String::new(), &[])
+    }
+}
+
+// printed operand and the level it binds at, a deref keeps its operand for ->
+struct Part {
+    text: String,
+    level: u8,
+    deref: Option<Box<Part>>,
+}
+
+impl Part {
+    fn new(text: String, level: u8) -> Self {
+        Part {
+            text,
+            level,
+            deref: None,
+        }
+    }
+
+    // parens only when it binds looser than the slot allows
+    fn wrap(self, max: u8) -> String {
+        if self.level > max {
+            format!("({})", self.text)
+        } else {
+            self.text
+        }
+    }
+}
+
+fn op_str(op: Op) -> &'static str {
+    match op {
+        Op::UnaryMinus | Op::Minus => "-",
+        Op::Not => "!",
+        Op::Deref | Op::Multiply => "*",
+        Op::AddrOf => "&",
+        Op::Divide => "/",
+        Op::Modulo => "%",
+        Op::Add => "+",
+        Op::GreaterEq => ">=",
+        Op::GreaterThan => ">",
+        Op::LessEq => "<=",
+        Op::LessThan => "<",
+        Op::Eq => "==",
+        Op::NotEq => "!=",
+        Op::And => "&&",
+        Op::Or => "||",
+        Op::Paren | Op::Index => panic!("{:?} has no symbol", op),
+    }
+}
+
+// rebuild infix from the postfix stack
+fn expr_part(ex: &ExprNd) -> Part {
+    let mut st: Vec<Part> = Vec::new();
+    for it in ex.stack.iter() {
+        let part = match it {
+            CalcItem::Factor(f) => factor_part(f),
+            CalcItem::Op(Op::Index) => {
+                let idx = st.pop().unwrap();
+                let arr = st.pop().unwrap();
+                let text = format!("{}[{}]", arr.wrap(POSTFIX_LEVEL), idx.text);
+                Part::new(text, POSTFIX_LEVEL)
+            }
+            CalcItem::Op(op) if get_op_param_num(*op) == 1 => {
+                let a = st.pop().unwrap();
+                let operand = Part::new(a.text.clone(), a.level);
+                let sym = op_str(*op);
+                let text = a.wrap(UNARY_LEVEL);
+                // - -x and & &x must not lex as one token pair
+                let sep = match *op {
+                    Op::UnaryMinus | Op::AddrOf if text.starts_with(sym) => " ",
+                    _ => "",
+                };
+                let mut part = Part::new(format!("{}{}{}", sym, sep, text), UNARY_LEVEL);
+                if *op == Op::Deref {
+                    part.deref = Some(Box::new(operand));
+                }
+                part
+            }
+            CalcItem::Op(op) => {
+                let b = st.pop().unwrap();
+                let a = st.pop().unwrap();
+                let level = get_op_level(*op);
+                let text = format!("{} {} {}", a.wrap(level), op_str(*op), b.wrap(level - 1));
+                Part::new(text, level)
+            }
+            CalcItem::Member(name) => {
+                let a = st.pop().unwrap();
+                let text = match a.deref {
+                    Some(p) => format!("{}->{}", p.wrap(POSTFIX_LEVEL), name),
+                    None => format!("{}.{}", a.wrap(POSTFIX_LEVEL), name),
+                };
+                Part::new(text, POSTFIX_LEVEL)
+            }
+            CalcItem::Cast(ty) => {
+                let a = st.pop().unwrap();
+                let text = format!("({}){}", ty.print(), a.wrap(UNARY_LEVEL));
+                Part::new(text, UNARY_LEVEL)
+            }
+        };
+        st.push(part);
+    }
+    st.pop().unwrap()
+}
+
+fn factor_part(f: &FactorNd) -> Part {
+    match f {
+        FactorNd::Var(v) => Part::new(v.name.clone(), 0),
+        FactorNd::Value(Value::Int(n)) if *n < 0 => Part::new(n.to_string(), UNARY_LEVEL),
+        FactorNd::Value(Value::Int(n)) => Part::new(n.to_string(), 0),
+        FactorNd::Value(Value::Void) => Part::new(String::new(), 0),
+        FactorNd::Str(s) => Part::new(quote(s), 0),
+        FactorNd::Func(n) => Part::new(format!("{}({})", n.var.name, args(&n.params)), 0),
+        FactorNd::Indirect(n) => indirect_part(n),
+        FactorNd::Sizeof(n) => sizeof_part(n),
+        FactorNd::Va(n) => Part::new(va_text(n), 0),
+    }
+}
+
+fn indirect_part(n: &IndirectCallNd) -> Part {
+    let callee = expr_part(&n.callee).wrap(POSTFIX_LEVEL);
+    Part::new(format!("{}({})", callee, args(&n.params)), POSTFIX_LEVEL)
+}
+
+fn sizeof_part(n: &SizeofNd) -> Part {
+    match &n.arg {
+        SizeofArg::Type(ty) => Part::new(format!("sizeof({})", ty.print()), 0),
+        SizeofArg::Expr(e) => {
+            let text = expr_part(e).wrap(UNARY_LEVEL);
+            let sep = if text.starts_with('(') { "" } else { " " };
+            Part::new(format!("sizeof{}{}", sep, text), UNARY_LEVEL)
+        }
+    }
+}
+
+fn va_text(n: &VaNd) -> String {
+    match n {
+        VaNd::Start(ap, last) => format!("va_start({}, {})", ap.name, last.name),
+        VaNd::Arg(ap, ty) => format!("va_arg({}, {})", ap.name, ty.print()),
+        VaNd::End(ap) => format!("va_end({})", ap.name),
+    }
+}
+
+fn args(params: &[ExprNd]) -> String {
+    let v: Vec<String> = params.iter().map(|p| p.print()).collect();
+    v.join(", ")
+}
+
+// octal escapes are padded so a following digit is not taken in
+fn quote(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            '\\' => res.push_str("\\\\"),
+            '"' => res.push_str("\\\""),
+            ' '..='~' => res.push(c),
+            _ => res.push_str(&format!("\\{:03o}", c as u32)),
+        }
+    }
+    res.push('"');
+    res
+}
+
+// C declarators read inside out, bounds are the unresolved array sizes, outer first
+fn declarator(ty: &Type, inner: String, bounds: &[Option<ExprNd>]) -> String {
+    match ty {
+        Type::Array(t, n) => {
+            let (dim, rest) = match bounds.split_first() {
+                Some((Some(e), rest)) => (e.print(), rest),
+                Some((None, rest)) => (n.to_string(), rest),
+                None => (n.to_string(), bounds),
+            };
+            declarator(t, format!("{}[{}]", inner, dim), rest)
+        }
+        Type::Ptr(t) => pointer(t, format!("*{}", inner)),
+        Type::Const(t) if t.is_ptr() => match t.as_ref() {
+            Type::Ptr(t) if inner.is_empty() => pointer(t, "*const".to_string()),
+            Type::Ptr(t) => pointer(t, format!("*const {}", inner)),
+            _ => unreachable!(),
+        },
+        Type::Const(t) => format!("const {}", declarator(t, inner, bounds)),
+        Type::Func(v, variadic) => {
+            let (ret, params) = v.split_last().unwrap();
+            let mut params: Vec<String> = params.iter().map(|t| t.print()).collect();
+            if *variadic {
+                params.push("...".to_string());
+            }
+            declarator(ret, format!("{}({})", inner, params.join(", ")), &[])
+        }
+        Type::Void | Type::Int | Type::Char | Type::Struct(_) => {
+            let base = match ty {
+                Type::Void => "void".to_string(),
+                Type::Int => "int".to_string(),
+                Type::Char => "char".to_string(),
+                // an anonymous struct is only reachable through its typedef
+                Type::Struct(s) => match StructNd::typedef_of(s) {
+                    Some(name) => name.to_string(),
+                    None => format!("struct {}", s),
+                },
+                _ => unreachable!(),
+            };
+            if inner.is_empty() {
+                base
+            } else {
+                format!("{} {}", base, inner)
+            }
+        }
+    }
+}
+
+// (*name) binds before the [] or () of what it points to
+fn pointer(t: &Type, inner: String) -> String {
+    match t {
+        Type::Func(..) | Type::Array(..) => declarator(t, format!("({})", inner), &[]),
+        _ => declarator(t, inner, &[]),
+    }
+}
+
+fn declare(n: &DeclareNd) -> String {
+    let storage = match n.storage {
+        StorageClass::Static => "static ",
+        StorageClass::Extern => "extern ",
+        StorageClass::Auto => "",
+    };
+    let decl = declarator(&n.ty, n.var.name.clone(), &n.bounds);
+    match &n.expr {
+        Some(e) => format!("{}{} = {}", storage, decl, e.print()),
+        None if !n.list.is_empty() => format!("{}{} = {{{}}}", storage, decl, args(&n.list)),
+        None => format!("{}{}", storage, decl),
+    }
+}
+
+// functions and type definitions are set apart by a blank line
+fn is_big(n: &GItemNd) -> bool {
+    match n {
+        GItemNd::Func(f) => f.is_impl(),
+        GItemNd::Struct(_) | GItemNd::Enum(_) => true,
+        GItemNd::Typedef(t) => t.def.is_some(),
+        GItemNd::Declare(_) => false,
+    }
+}
+
+impl Printer {
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn line(&mut self, s: &str) {
+        self.indent();
+        self.out.push_str(s);
+        self.out.push('\n');
+    }
+
+    // { ... } with the closing brace left open on its line
+    fn block_body(&mut self, n: &BlockNd) {
+        self.out.push_str("{\n");
+        self.depth += 1;
+        for it in n.items.iter() {
+            self.visit_item(it);
+        }
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+    }
+
+    // body of if, else and while, true when it ends inside a line
+    fn body(&mut self, n: &ItemNd) -> bool {
+        match n {
+            ItemNd::Block(b) => {
+                self.out.push(' ');
+                self.block_body(b);
+                true
+            }
+            _ => {
+                self.out.push('\n');
+                self.depth += 1;
+                self.visit_item(n);
+                self.depth -= 1;
+                false
+            }
+        }
+    }
+
+    fn if_chain(&mut self, n: &IfNd) {
+        self.out.push_str(&format!("if ({})", n.expr.print()));
+        let mut open = self.body(&n.item);
+        if let Some(els) = &n.els {
+            if open {
+                self.out.push_str(" else");
+            } else {
+                self.indent();
+                self.out.push_str("else");
+            }
+            match els {
+                ElsNd::If(n) => {
+                    self.out.push(' ');
+                    return self.if_chain(n);
+                }
+                ElsNd::Item(it) => open = self.body(it),
+            }
+        }
+        if open {
+            self.out.push('\n');
+        }
+    }
+
+    fn struct_body(&mut self, n: &StructNd) {
+        match StructNd::typedef_of(&n.name) {
+            Some(_) => self.out.push_str("struct {\n"),
+            None => self.out.push_str(&format!("struct {} {{\n", n.name)),
+        }
+        self.depth += 1;
+        for f in n.fields.iter() {
+            self.visit_declare(f);
+        }
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+    }
+}
+
+impl Visitor for Printer {
+    fn visit_root(&mut self, n: &RootNd) {
+        for (i, it) in n.items.iter().enumerate() {
+            if i > 0 && (is_big(it) || is_big(&n.items[i - 1])) {
+                self.out.push('\n');
+            }
+            self.visit_gitem(it);
+        }
+    }
+
+    fn visit_func(&mut self, n: &FuncNd) {
+        let mut params: Vec<String> = n
+            .params
+            .iter()
+            .map(|(ty, v)| match v {
+                Some(v) => declarator(ty, v.name.clone(), &[]),
+                None => ty.print(),
+            })
+            .collect();
+        if n.variadic {
+            params.push("...".to_string());
+        }
+        let head = format!("{}({})", n.var.name, params.join(", "));
+        self.indent();
+        self.out.push_str(&declarator(&n.ret_ty, head, &[]));
+        match &n.block {
+            Some(b) => {
+                self.out.push(' ');
+                self.block_body(b);
+                self.out.push('\n');
+            }
+            None => self.out.push_str(";\n"),
+        }
+    }
+
+    fn visit_struct(&mut self, n: &StructNd) {
+        self.indent();
+        self.struct_body(n);
+        self.out.push_str(";\n");
+    }
+
+    fn visit_enum(&mut self, n: &EnumNd) {
+        match &n.name {
+            Some(name) => self.line(&format!("enum {} {{", name)),
+            None => self.line("enum {"),
+        }
+        self.depth += 1;
+        for (v, e) in n.items.iter() {
+            match e {
+                Some(e) => self.line(&format!("{} = {},", v.name, e.print())),
+                None => self.line(&format!("{},", v.name)),
+            }
+        }
+        self.depth -= 1;
+        self.line("};");
+    }
+
+    fn visit_typedef(&mut self, n: &TypedefNd) {
+        let decl = declarator(&n.ty, n.name.clone(), &[]);
+        self.indent();
+        self.out.push_str("typedef ");
+        match &n.def {
+            // the body takes the place of the struct name
+            Some(s) => {
+                self.struct_body(s);
+                let base = Type::Struct(s.name.clone()).print();
+                self.out.push_str(decl.strip_prefix(&base).unwrap_or(&decl));
+            }
+            None => self.out.push_str(&decl),
+        }
+        self.out.push_str(";\n");
+    }
+
+    fn visit_declare(&mut self, n: &DeclareNd) {
+        self.line(&format!("{};", declare(n)));
+    }
+
+    fn visit_block(&mut self, n: &BlockNd) {
+        self.indent();
+        self.block_body(n);
+        self.out.push('\n');
+    }
+
+    fn visit_stmt(&mut self, n: &StmtNd) {
+        match n {
+            StmtNd::Assign(n) => self.visit_assign(n),
+            StmtNd::Declare(n) => self.visit_declare(n),
+            StmtNd::Expr(e) => self.line(&format!("{};", e.print())),
+            StmtNd::Print(v) => self.line(&format!("{};", v.name)),
+            StmtNd::Empty => self.line(";"),
+        }
+    }
+
+    fn visit_assign(&mut self, n: &AssignNd) {
+        self.line(&format!("{} = {};", n.lhs.print(), n.expr.print()));
+    }
+
+    fn visit_if(&mut self, n: &IfNd) {
+        self.indent();
+        self.if_chain(n);
+    }
+
+    fn visit_while(&mut self, n: &WhileNd) {
+        self.indent();
+        self.out.push_str(&format!("while ({})", n.expr.print()));
+        if self.body(&n.item) {
+            self.out.push('\n');
+        }
+    }
+
+    fn visit_return(&mut self, n: &ReturnNd) {
+        match &n.expr {
+            Some(e) => self.line(&format!("return {};", e.print())),
+            None => self.line("return;"),
+        }
+    }
+
+    fn visit_break(&mut self, _n: &BreakNd) {
+        self.line("break;");
+    }
+
+    fn visit_continue(&mut self, _n: &ContinueNd) {
+        self.line("continue;");
+    }
+
+    fn visit_expr(&mut self, n: &ExprNd) {
+        self.out.push_str(&expr_part(n).text);
+    }
+
+    fn visit_factor(&mut self, n: &FactorNd) {
+        self.out.push_str(&factor_part(n).text);
+    }
+
+    fn visit_var(&mut self, n: &VarNd) {
+        self.out.push_str(&n.name);
+    }
+
+    fn visit_func_call(&mut self, n: &FuncCallNd) {
+        self.out
+            .push_str(&format!("{}({})", n.var.name, args(&n.params)));
+    }
+
+    fn visit_indirect_call(&mut self, n: &IndirectCallNd) {
+        self.out.push_str(&indirect_part(n).text);
+    }
+
+    fn visit_sizeof(&mut self, n: &SizeofNd) {
+        self.out.push_str(&sizeof_part(n).text);
+    }
+
+    fn visit_va(&mut self, n: &VaNd) {
+        self.out.push_str(&va_text(n));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, Print};
+    use crate::core::parser::parse;
+    use crate::core::types::nodes::RootNd;
+    use crate::utils::load_code_from_file;
+    use std::fs;
+
+    // node ids differ between parses
+    fn strip_ids(s: &str) -> String {
+        let mut res = String::new();
+        let mut rest = s;
+        while let Some(i) = rest.find("NodeId(") {
+            res.push_str(&rest[..i]);
+            rest = &rest[i..];
+            rest = &rest[rest.find(')').unwrap() + 1..];
+        }
+        res.push_str(rest);
+        res
+    }
+
+    #[test]
+    fn test_format_expr() {
+        let code = "int main() { int a = (1 + 2) * 3; int b = 1 + (2 * 3); \
+                    int c = (1 - 2) - 3; int d = 1 - (2 - 3); int e = - -a; \
+                    int *p = &a; int f = -*p + !(a && b || c); \
+                    char g = (char)(a + b); int h = sizeof(int *) + sizeof (*p + 1); }";
+        let expected = "\
+int main() {
+    int a = (1 + 2) * 3;
+    int b = 1 + 2 * 3;
+    int c = 1 - 2 - 3;
+    int d = 1 - (2 - 3);
+    int e = - -a;
+    int *p = &a;
+    int f = -*p + !(a && b || c);
+    char g = (char)(a + b);
+    int h = sizeof(int *) + sizeof(*p + 1);
+}
+";
+        assert_eq!(format(code).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_decl() {
+        let code = "struct P { int x; int y; }; typedef struct { int v; } *Box; \
+                    static const char *names[2]; int (*ops[4])(int, int); \
+                    int apply(int (*f)(int, ...), int n, ...); \
+                    int get(struct P *p) { if (p->x) return (*p).y; else if (p[1].x) { return 1; } while (1) break; }";
+        let expected = "\
+struct P {
+    int x;
+    int y;
+};
+
+typedef struct {
+    int v;
+} *Box;
+
+static const char *names[2];
+int (*ops[4])(int, int);
+int apply(int (*f)(int, ...), int n, ...);
+
+int get(struct P *p) {
+    if (p->x)
+        return p->y;
+    else if (p[1].x) {
+        return 1;
+    }
+    while (1)
+        break;
+}
+";
+        assert_eq!(format(code).unwrap(), expected);
+        let nd = parse::<RootNd>(code).unwrap();
+        assert_eq!(
+            nd.items[1].print(),
+            "typedef struct {\n    int v;\n} *Box;\n"
+        );
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let mut files = vec!["example/gcd.c".to_string()];
+        for dir in ["test_cfiles/vm", "test_cfiles/analyzer"].iter() {
+            for e in fs::read_dir(dir).unwrap() {
+                files.push(e.unwrap().path().to_str().unwrap().to_string());
+            }
+        }
+        files.sort();
+        let mut n = 0;
+        for f in files.iter() {
+            let code = load_code_from_file(f);
+            let nd = match parse::<RootNd>(&code) {
+                Ok(nd) => nd,
+                Err(_) => continue,
+            };
+            let out = nd.print();
+            let again = parse::<RootNd>(&out).unwrap_or_else(|e| panic!("{}: {}\n{}", f, e, out));
+            let a = strip_ids(&format!("{:?}", nd));
+            let b = strip_ids(&format!("{:?}", again));
+            assert_eq!(a, b, "{}", f);
+            assert_eq!(again.print(), out, "{}", f);
+            n += 1;
+        }
+        assert!(n > 20);
+    }
+}
@@ -0,0 +1,364 @@
+use super::analyzer::{self, Analyzer};
+use super::compiler;
+use super::lexer::lex_source;
+use super::parser::parse_with_typedefs;
+use super::types::nodes::*;
+use super::types::visit::Visitor;
+use super::types::{
+    CalcItem, ErrKind, Error, RuntimeError, SemanticError, StorageClass, Token, Type, WarnKind,
+};
+use super::vm::{Input, Output, VmBuilder, VM};
+use std::collections::HashMap;
+
+// Each input is analyzed and compiled on top of the earlier ones and runs
+// on the same vm, so globals, the heap and functions live on. Globals never
+// move, a function implemented again is relinked for all its callers
+pub struct Repl {
+    scope: analyzer::Context,
+    cxt: compiler::Context,
+    vm: VM,
+    typedefs: HashMap<String, Type>,
+    inputs: usize, // statements and expressions, each runs as a func
+    warned: usize,
+}
+
+fn semantic(kind: ErrKind) -> Error {
+    Error::Semantic(SemanticError { kind })
+}
+
+// a failed parse of these is reported as a definition
+fn starts_definition(input: &str, typedefs: &HashMap<String, Type>) -> bool {
+    match lex_source(input).ok().and_then(|tks| tks.first().cloned()) {
+        Some(Token::Name(s)) => typedefs.contains_key(&s),
+        Some(t) => matches!(
+            t,
+            Token::Type(_)
+                | Token::Struct
+                | Token::Enum
+                | Token::Typedef
+                | Token::Static
+                | Token::Extern
+                | Token::Const
+        ),
+        None => false,
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl::with_vm(&VmBuilder::new()).expect("default sizes fit")
+    }
+
+    pub(crate) fn with_vm(opts: &VmBuilder) -> Result<Self, Error> {
+        let mut scope = analyzer::Context::new();
+        scope.allow_redefine();
+        let cxt = compiler::Context::new(scope.snapshot(0));
+        let vm = opts.build(cxt.program(0))?;
+        Ok(Repl {
+            scope,
+            cxt,
+            vm,
+            typedefs: HashMap::new(),
+            inputs: 0,
+            warned: 0,
+        })
+    }
+
+    // Global declarations and definitions, or statements whose last one,
+    // if an expression, gives the returned value, its `;` may be left out.
+    // An input that fails before it runs leaves the session as it was
+    pub fn eval(&mut self, input: &str) -> Result<Option<i32>, Error> {
+        let saved = (self.scope.clone(), self.cxt.clone(), self.typedefs.clone());
+        match self.compile_input(input.trim()) {
+            Ok(Some(echo)) => {
+                self.vm.execute()?;
+                Ok(if echo { self.vm.exit_code() } else { None })
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.scope = saved.0;
+                self.cxt = saved.1;
+                self.typedefs = saved.2;
+                Err(e)
+            }
+        }
+    }
+
+    // warnings of the inputs since the last call
+    pub fn take_warnings(&mut self) -> Vec<WarnKind> {
+        let ws = self.scope.warnings();
+        let new = ws.get(self.warned..).unwrap_or(&[]).to_vec();
+        self.warned = ws.len();
+        new
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn set_output<O: Output + 'static>(&mut self, output: O) {
+        self.vm.set_output(output);
+    }
+
+    pub fn set_input<I: Input + 'static>(&mut self, input: I) {
+        self.vm.set_input(input);
+    }
+
+    // Some when there is code to run, true if it returns the value
+    fn compile_input(&mut self, input: &str) -> Result<Option<bool>, Error> {
+        if let Ok(ex) = parse_with_typedefs::<ExprNd>(input, &mut self.typedefs) {
+            let block = BlockNd::new(vec![ItemNd::Stmt(StmtNd::Expr(ex))]);
+            return self.compile_value(block).map(Some);
+        }
+        let root_err = match parse_with_typedefs::<RootNd>(input, &mut self.typedefs) {
+            Ok(nd) => {
+                let saved = (self.scope.clone(), self.cxt.clone());
+                return match self.define(&nd) {
+                    Err(Error::Semantic(SemanticError {
+                        kind: ErrKind::GlobalNeedConst,
+                    })) => {
+                        self.scope = saved.0;
+                        self.cxt = saved.1;
+                        self.define_then_init(nd).map(Some)
+                    }
+                    r => r.map(|_| None),
+                };
+            }
+            Err(e) => e,
+        };
+        // the last statement may leave out its `;`
+        let code = if input.ends_with(';') || input.ends_with('}') {
+            format!("{{{}}}", input)
+        } else {
+            format!("{{{};}}", input)
+        };
+        match parse_with_typedefs::<BlockNd>(&code, &mut self.typedefs) {
+            Ok(nd) => self.compile_value(nd).map(Some),
+            Err(_) if starts_definition(input, &self.typedefs) => Err(root_err),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn define(&mut self, nd: &RootNd) -> Result<(), Error> {
+        self.scope.skip_to(self.cxt.memory_len());
+        nd.analyze(&mut self.scope).map_err(semantic)?;
+        self.cxt.update(self.scope.snapshot(0));
+        self.cxt.visit_root(nd);
+        Ok(())
+    }
+
+    // Globals of the session may start from other globals (`int y = x;`),
+    // those initializers become assignments run once the globals exist
+    fn define_then_init(&mut self, mut nd: RootNd) -> Result<bool, Error> {
+        let mut inits = Vec::new();
+        for item in nd.items.iter_mut() {
+            let d = match item {
+                GItemNd::Declare(d) => d,
+                _ => continue,
+            };
+            let later = d.storage != StorageClass::Extern
+                && !d.ty.is_const()
+                && d.bounds.is_empty()
+                && d.expr
+                    .as_ref()
+                    .map_or(false, |ex| ex.try_to_str().is_none());
+            if !later {
+                continue;
+            }
+            if let Some(ex) = d.expr.take() {
+                let lhs = ExprNd::new(vec![CalcItem::Factor(FactorNd::Var(VarNd::new(
+                    d.var.name.clone(),
+                )))]);
+                inits.push(ItemNd::Stmt(StmtNd::Assign(AssignNd::new(lhs, ex))));
+            }
+        }
+        self.define(&nd)?;
+        self.compile_entry(Type::Void, BlockNd::new(inits))?;
+        Ok(false)
+    }
+
+    // A final expression statement gives the value of the input, the type
+    // of the expression, found on a copy, is the return type
+    fn compile_value(&mut self, mut block: BlockNd) -> Result<bool, Error> {
+        let ex = match block.items.last_mut() {
+            Some(ItemNd::Stmt(StmtNd::Expr(ex))) => ex.clone(),
+            // a bare name is a value too
+            Some(ItemNd::Stmt(StmtNd::Print(v))) => {
+                let ex = ExprNd::new(vec![CalcItem::Factor(FactorNd::Var(v.clone()))]);
+                block.items.pop();
+                block.items.push(ItemNd::Stmt(StmtNd::Expr(ex.clone())));
+                ex
+            }
+            _ => {
+                self.compile_entry(Type::Void, block)?;
+                return Ok(false);
+            }
+        };
+        let probe = FuncNd::new(
+            Type::Void,
+            VarNd::new(String::new()),
+            Vec::new(),
+            false,
+            Some(block.clone()),
+        );
+        let mut scope = self.scope.clone();
+        probe.analyze(&mut scope).map_err(semantic)?;
+        let ty = scope.table().expr_type(ex.id).unqualified().clone();
+        if ty.is_scalar() {
+            block.items.pop();
+            block.items.push(ItemNd::Return(ReturnNd::new(Some(ex))));
+            self.compile_entry(ty, block)?;
+            Ok(true)
+        } else {
+            self.compile_entry(Type::Void, block)?;
+            Ok(false)
+        }
+    }
+
+    // the input becomes a func of its own, the vm is loaded to call it
+    fn compile_entry(&mut self, ty: Type, block: BlockNd) -> Result<(), Error> {
+        self.inputs += 1;
+        let name = format!("<input {}>", self.inputs);
+        let f = FuncNd::new(ty, VarNd::new(name), Vec::new(), false, Some(block));
+        self.scope.skip_to(self.cxt.memory_len());
+        f.analyze(&mut self.scope).map_err(semantic)?;
+        self.scope.check_defined().map_err(semantic)?;
+        let id = self.scope.table().symbol(f.var.id);
+        self.cxt.update(self.scope.snapshot(id));
+        self.cxt.visit_func(&f);
+        let prog = self.cxt.program(id);
+        let pc = prog.start_pc;
+        self.vm
+            .reload(prog)
+            .map_err(|kind| RuntimeError { kind, pc })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Repl;
+    use crate::core::types::{ErrKind, WarnKind};
+    use crate::core::vm::BufferOutput;
+
+    #[test]
+    fn test_repl() {
+        let mut repl = Repl::new();
+        let buf = BufferOutput::new();
+        repl.set_output(buf.clone());
+        assert_eq!(repl.eval("int n = 4;").unwrap(), None);
+        assert_eq!(repl.eval("char *s = \"hi\";").unwrap(), None);
+        assert_eq!(repl.eval("int sq(int x) { return x * x; }").unwrap(), None);
+        assert_eq!(repl.eval("sq(n) + 1").unwrap(), Some(17));
+        assert_eq!(repl.eval("n = sq(n)").unwrap(), None);
+        assert_eq!(repl.eval("n").unwrap(), Some(16));
+        // the `;` of a final expression doesn't drop its value
+        assert_eq!(repl.eval("sq(3);").unwrap(), Some(9));
+        assert_eq!(repl.eval("n;").unwrap(), Some(16));
+        assert_eq!(repl.eval("n = 2; sq(n);").unwrap(), Some(4));
+        assert_eq!(repl.eval("n = 16;").unwrap(), None);
+
+        // later globals and literals keep earlier addresses
+        repl.eval("int m[3];").unwrap();
+        repl.eval("typedef struct P { int x; int y; } P;").unwrap();
+        repl.eval("P p; p.y = 5; m[2] = p.y;").unwrap();
+        assert_eq!(repl.eval("m[2] + n").unwrap(), Some(21));
+        assert_eq!(repl.eval("s[1]").unwrap(), Some('i' as i32));
+        assert_eq!(repl.eval("puts(\"yo\");").unwrap(), Some(0));
+
+        // callers get the new code
+        repl.eval("int twice(int x) { return sq(x) + sq(x); }")
+            .unwrap();
+        assert_eq!(repl.eval("twice(3)").unwrap(), Some(18));
+        repl.eval("int sq(int x) { return x + x; }").unwrap();
+        assert_eq!(repl.eval("twice(3)").unwrap(), Some(12));
+
+        // locals, loops, statics and the heap
+        let code = "int i = 0; while (i < 4) { n = n + i; i = i + 1; }";
+        assert_eq!(repl.eval(code).unwrap(), None);
+        assert_eq!(repl.eval("n").unwrap(), Some(22));
+        repl.eval("int next() { static int c = 10; c = c + 1; return c; }")
+            .unwrap();
+        assert_eq!(repl.eval("next();").unwrap(), Some(11));
+        assert_eq!(repl.eval("next()").unwrap(), Some(12));
+        repl.eval("int *h;").unwrap();
+        repl.eval("h = malloc(2); h[1] = 7;").unwrap();
+        assert_eq!(repl.eval("h[1]").unwrap(), Some(7));
+        assert_eq!(buf.stdout(), "yo\n");
+
+        // globals set from other globals
+        repl.eval("int x = 3;").unwrap();
+        assert_eq!(repl.eval("int y = x + 1;").unwrap(), None);
+        assert_eq!(repl.eval("y").unwrap(), Some(4));
+        repl.eval("int *p = &x; int k = 2;").unwrap();
+        repl.eval("*p = 9;").unwrap();
+        assert_eq!(repl.eval("x * k").unwrap(), Some(18));
+        assert!(repl.eval("const int c = x;").is_err());
+    }
+
+    #[test]
+    fn test_repl_errors() {
+        let mut repl = Repl::new();
+        repl.eval("int a = 1;").unwrap();
+        // nothing of a failed input is kept
+        let e = repl.eval("int b = 2; int a = 3;").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::ReDeclare));
+        assert_eq!(repl.eval("int b = 5;").unwrap(), None);
+        assert_eq!(
+            repl.eval("x + 1").unwrap_err().kind(),
+            Some(ErrKind::NoDeclare)
+        );
+        assert_eq!(
+            repl.eval("int (").unwrap_err().kind(),
+            Some(ErrKind::ParseErr)
+        );
+        assert_eq!(
+            repl.eval("a = ;").unwrap_err().kind(),
+            Some(ErrKind::ParseErr)
+        );
+        let e = repl.eval("int sq(int x) { return x; } char sq(char c) { return c; }");
+        assert_eq!(e.unwrap_err().kind(), Some(ErrKind::TypeErr));
+        assert_eq!(
+            repl.eval("puts(1)").unwrap_err().kind(),
+            Some(ErrKind::TypeErr)
+        );
+        let e = repl.eval("int puts(char *s) { return 0; }").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::ReImpl));
+
+        // calls wait for the impl, then the session goes on
+        repl.eval("int f(int x);").unwrap();
+        assert_eq!(
+            repl.eval("f(1)").unwrap_err().kind(),
+            Some(ErrKind::FuncNoImpl)
+        );
+        repl.eval("int f(int x) { return a / x; }").unwrap();
+        assert_eq!(
+            repl.eval("f(0)").unwrap_err().kind(),
+            Some(ErrKind::DivideZero)
+        );
+        assert_eq!(repl.eval("f(1) + b").unwrap(), Some(6));
+
+        repl.eval("char c = 300;").unwrap();
+        assert_eq!(repl.take_warnings(), vec![WarnKind::Narrowing]);
+        assert!(repl.take_warnings().is_empty());
+
+        // a budget for each input
+        let mut repl = crate::core::vm::VmBuilder::new()
+            .max_instructions(50)
+            .repl()
+            .unwrap();
+        for _ in 0..20 {
+            assert_eq!(repl.eval("1 + 2").unwrap(), Some(3));
+        }
+        assert_eq!(
+            repl.eval("while (1) {}").unwrap_err().kind(),
+            Some(ErrKind::InstructionLimit(50))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod builtin;
 mod code;
 mod err;
 mod infos;
@@ -5,16 +6,24 @@ mod layout;
 pub mod nodes;
 mod op;
 mod seq;
+mod table;
 mod token;
+pub mod visit;
 
+pub use builtin::{Builtin, BUILTINS};
 pub use code::{Code, CodeAddr, MemAddr};
-pub use err::ErrKind;
-pub use infos::{FuncInfo, ScopeInfo, VarInfo};
+pub use err::{
+    ErrKind, Error, IoError, LexError, ParseError, RuntimeError, SemanticError, WarnKind,
+};
+pub use infos::{FieldInfo, FuncInfo, ScopeInfo, StorageClass, StructInfo, VarInfo};
 pub use layout::Layout;
 pub use nodes::*;
-pub use op::{calc_op_1, calc_op_2, get_calc_stack, get_op_param_num, CalcItem, Op};
+pub use op::{
+    calc_op_1, calc_op_2, calc_trunc, eat_call_args, get_calc_stack, get_op_level,
+    get_op_param_num, CalcItem, Op,
+};
 pub use seq::{SeqPack, Sequence};
+pub use table::NodeTable;
 pub use token::{
-    get_default_value, get_token_from_char, get_token_from_word, get_type_size, get_value_type,
-    match_value_type, Token, Type, Value,
+    get_token_from_char, get_token_from_word, get_type_size, get_value_type, Token, Type, Value,
 };
@@ -0,0 +1,71 @@
+use super::token::Type;
+
+// Functions provided by the vm, declared before user code
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Builtin {
+    Puts,
+    Putchar,
+    Printf,
+    Malloc,
+    Calloc,
+    Realloc,
+    Free,
+    Getchar,
+    ReadInt,
+    Scanf,
+}
+
+pub const BUILTINS: [Builtin; 10] = [
+    Builtin::Puts,
+    Builtin::Putchar,
+    Builtin::Printf,
+    Builtin::Malloc,
+    Builtin::Calloc,
+    Builtin::Realloc,
+    Builtin::Free,
+    Builtin::Getchar,
+    Builtin::ReadInt,
+    Builtin::Scanf,
+];
+
+impl Builtin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Puts => "puts",
+            Builtin::Putchar => "putchar",
+            Builtin::Printf => "printf",
+            Builtin::Malloc => "malloc",
+            Builtin::Calloc => "calloc",
+            Builtin::Realloc => "realloc",
+            Builtin::Free => "free",
+            Builtin::Getchar => "getchar",
+            Builtin::ReadInt => "read_int",
+            Builtin::Scanf => "scanf",
+        }
+    }
+
+    pub fn func_ty(&self) -> Type {
+        let void_ptr = Type::Ptr(Box::new(Type::Void));
+        match self {
+            Builtin::Puts => Type::Func(
+                vec![Type::Ptr(Box::new(Type::Char.into_const())), Type::Int],
+                false,
+            ),
+            Builtin::Putchar => Type::Func(vec![Type::Int, Type::Int], false),
+            Builtin::Printf => Type::Func(
+                vec![Type::Ptr(Box::new(Type::Char.into_const())), Type::Int],
+                true,
+            ),
+            Builtin::Malloc => Type::Func(vec![Type::Int, void_ptr], false),
+            Builtin::Calloc => Type::Func(vec![Type::Int, Type::Int, void_ptr], false),
+            Builtin::Realloc => Type::Func(vec![void_ptr.clone(), Type::Int, void_ptr], false),
+            Builtin::Free => Type::Func(vec![void_ptr, Type::Void], false),
+            Builtin::Getchar | Builtin::ReadInt => Type::Func(vec![Type::Int], false),
+            Builtin::Scanf => Type::Func(
+                vec![Type::Ptr(Box::new(Type::Char.into_const())), Type::Int],
+                true,
+            ),
+        }
+    }
+}
@@ -1,12 +1,15 @@
+use super::builtin::Builtin;
 use super::op::Op;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MemAddr {
     Direct(usize),
     Indirect(usize),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CodeAddr {
     Direct(usize),
     NameStart(u32), // scope
@@ -14,15 +17,28 @@ pub enum CodeAddr {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Code {
     Push(MemAddr),
+    PushAddr(MemAddr),
     PushValue(i32),
+    Reserve(usize),
     Pop(usize),
     PopMov(MemAddr),
+    Load(usize),
+    Store(usize),
     Op(Op),
+    Trunc(usize),
+    PushFunc(CodeAddr),
     Call(CodeAddr, usize),
+    CallIndirect(usize), // target on top of the params
+    Builtin(Builtin),
+    CallNative(usize, usize), // index into the natives of the vm, arg count
     Jump(CodeAddr),
     CondJump(CodeAddr),
     Print,
     Ret(usize),
+    RetVariadic(usize), // also drops the variadic args under the frame
+    VaStart,
+    VaArg(usize), // the cursor on top must point to that many cells of passed args
 }
@@ -1,6 +1,9 @@
+use super::token::{get_token_text, Token};
+use std::{error, fmt, io};
 use strum_macros::Display;
 
 #[derive(Debug, PartialEq, Clone, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ErrKind {
     LexErr,
     ParseErr,
@@ -10,10 +13,236 @@ pub enum ErrKind {
     NoDeclare,
     ReImpl,
     FuncNoImpl,
+    ExternNoDefine,
     NoMainFunc,
     TypeErr,
     GlobalNeedConst,
+    AssignConst,
+    ForeignTree, // compiling a tree with the analysis of another
 
-    StackOverFlow,
+    StackOverFlow(usize, String), // call depth, function running or called
+    StackUnderflow,
+    InvalidCode,
     DivideZero,
+    InvalidAddress,
+    WriteReadOnly,
+    BadFormat,
+    DoubleFree,
+    InvalidFree,
+    UseAfterFree,
+    // instructions run so far in each
+    InstructionLimit(u64),
+    Timeout(u64),
+    Interrupted(u64),
+    NativeErr(String), // what the native function returned
 }
+
+#[derive(Debug, PartialEq, Clone, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WarnKind {
+    Narrowing,
+}
+
+impl WarnKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            WarnKind::Narrowing => "narrowing conversion, the value is truncated",
+        }
+    }
+}
+
+impl ErrKind {
+    // human readable, Display stays the variant name
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ErrKind::LexErr => "invalid token",
+            ErrKind::ParseErr => "syntax error",
+            ErrKind::JumpNoLoop => "break or continue outside of a loop",
+            ErrKind::ReDeclare => "name declared twice in one scope",
+            ErrKind::NoDeclare => "use of an undeclared name",
+            ErrKind::ReImpl => "function implemented twice",
+            ErrKind::FuncNoImpl => "function declared but never implemented",
+            ErrKind::ExternNoDefine => "extern variable never defined",
+            ErrKind::NoMainFunc => "no main function",
+            ErrKind::TypeErr => "type mismatch",
+            ErrKind::GlobalNeedConst => "global initializer is not a constant",
+            ErrKind::AssignConst => "assignment to a const",
+            ErrKind::ForeignTree => "the analysis is of another tree",
+            ErrKind::StackOverFlow(..) => "stack overflow",
+            ErrKind::StackUnderflow => "stack underflow",
+            ErrKind::InvalidCode => "malformed code",
+            ErrKind::DivideZero => "division by zero",
+            ErrKind::InvalidAddress => "invalid memory access",
+            ErrKind::WriteReadOnly => "write to read-only memory",
+            ErrKind::BadFormat => "bad printf format",
+            ErrKind::DoubleFree => "double free",
+            ErrKind::InvalidFree => "free of a pointer that was not allocated",
+            ErrKind::UseAfterFree => "use after free",
+            ErrKind::InstructionLimit(_) => "instruction limit reached",
+            ErrKind::Timeout(_) => "time limit reached",
+            ErrKind::Interrupted(_) => "interrupted",
+            ErrKind::NativeErr(_) => "native function failed",
+        }
+    }
+}
+
+// lines are 1-based
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub line: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lex error at line {}: invalid token", self.line)
+    }
+}
+
+impl error::Error for LexError {}
+
+// the furthest token the parser got to, None at the end of input
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub near: Option<Token>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.near {
+            Some(t) => write!(
+                f,
+                "parse error at line {}: unexpected `{}`",
+                self.line,
+                get_token_text(t)
+            ),
+            None => write!(f, "parse error: unexpected end of input"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SemanticError {
+    pub kind: ErrKind,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "semantic error: {}", self.kind.describe())
+    }
+}
+
+impl error::Error for SemanticError {}
+
+// pc of the failing code
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError {
+    pub kind: ErrKind,
+    pub pc: usize,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "runtime error at code {}: {}",
+            self.pc,
+            self.kind.describe()
+        )?;
+        match &self.kind {
+            ErrKind::NativeErr(msg) => write!(f, ": {}", msg),
+            ErrKind::StackOverFlow(depth, func) if func.is_empty() => {
+                write!(f, " at call depth {}", depth)
+            }
+            ErrKind::StackOverFlow(depth, func) => {
+                write!(f, " at call depth {} in {}", depth, func)
+            }
+            ErrKind::InstructionLimit(n) | ErrKind::Timeout(n) | ErrKind::Interrupted(n) => {
+                write!(f, " after {} instructions", n)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl error::Error for RuntimeError {}
+
+#[derive(Debug)]
+pub struct IoError {
+    pub path: String,
+    pub source: io::Error,
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot read {}", self.path)
+    }
+}
+
+impl error::Error for IoError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Errors of the public api, one layer per stage
+#[derive(Debug)]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+    Semantic(SemanticError),
+    Runtime(RuntimeError),
+    Io(IoError),
+}
+
+impl Error {
+    // None for io errors
+    pub fn kind(&self) -> Option<ErrKind> {
+        match self {
+            Error::Lex(_) => Some(ErrKind::LexErr),
+            Error::Parse(_) => Some(ErrKind::ParseErr),
+            Error::Semantic(e) => Some(e.kind.clone()),
+            Error::Runtime(e) => Some(e.kind.clone()),
+            Error::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(e) => e.fmt(f),
+            Error::Parse(e) => e.fmt(f),
+            Error::Semantic(e) => e.fmt(f),
+            Error::Runtime(e) => e.fmt(f),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+// a transparent wrapper, the layer's message is already the display
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Lex(e) => e.source(),
+            Error::Parse(e) => e.source(),
+            Error::Semantic(e) => e.source(),
+            Error::Runtime(e) => e.source(),
+            Error::Io(e) => e.source(),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($e:ident => $v:ident),*) => {
+        $(impl From<$e> for Error {
+            fn from(e: $e) -> Self {
+                Error::$v(e)
+            }
+        })*
+    };
+}
+
+impl_from!(LexError => Lex, ParseError => Parse, SemanticError => Semantic, RuntimeError => Runtime, IoError => Io);
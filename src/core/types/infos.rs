@@ -1,37 +1,83 @@
+use super::builtin::Builtin;
+use super::layout::Layout;
 use super::token::Type;
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncInfo {
     pub id: u32,
     pub ty: Type,
     pub has_impl: bool,
+    pub builtin: Option<Builtin>,
+    pub native: Option<usize>, // index into the natives of the vm
 }
 
 impl FuncInfo {
     pub fn new(id: u32, ty: Type) -> Self {
         FuncInfo {
-            id: id,
-            ty: ty,
+            id,
+            ty,
             has_impl: false,
+            builtin: None,
+            native: None,
         }
     }
 }
 
+// Auto is a plain declaration, at file scope it has external linkage
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StorageClass {
+    Auto,
+    Static,
+    Extern,
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarInfo {
     pub id: u32,
     pub scope_id: u32,
     pub func_id: u32,
     pub ty: Type,
+    pub storage: StorageClass,
 }
 
 impl VarInfo {
     pub fn is_global(&self) -> bool {
         self.func_id == 0
     }
+
+    // globals and static locals live in program memory
+    pub fn in_memory(&self) -> bool {
+        self.is_global() || self.storage == StorageClass::Static
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldInfo {
+    pub name: String,
+    pub ty: Type,
+    pub layout: Layout,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StructInfo {
+    pub name: String,
+    pub fields: Vec<FieldInfo>,
+    pub size: usize,
+}
+
+impl StructInfo {
+    pub fn get_field(&self, name: &str) -> Option<&FieldInfo> {
+        self.fields.iter().find(|f| f.name == name)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ScopeInfo {
     pub id: u32,
     pub is_loop: bool,
@@ -1,22 +1,113 @@
-use super::op::{calc_op_1, calc_op_2, get_op_param_num, CalcItem};
+use super::infos::StorageClass;
+use super::op::{calc_op_1, calc_op_2, calc_trunc, get_op_param_num, CalcItem, Op};
+use super::table::NodeTable;
 use super::token::{Type, Value};
-use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Identifies a node in the tables filled by the analyzer, unique across trees
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(pub u32);
+
+static NEXT_NODE_ID: AtomicU32 = AtomicU32::new(1);
+
+impl NodeId {
+    pub fn new() -> Self {
+        NodeId(NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for NodeId {
+    fn default() -> Self {
+        NodeId::new()
+    }
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FactorNd {
     Var(VarNd),
     Value(Value),
+    Str(String),
     Func(FuncCallNd),
+    Indirect(IndirectCallNd),
+    Sizeof(SizeofNd),
+    Va(VaNd),
+}
+
+// va_start(ap, last), va_arg(ap, type), va_end(ap)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VaNd {
+    Start(VarNd, VarNd),
+    Arg(VarNd, Type),
+    End(VarNd),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SizeofArg {
+    Type(Type),
+    Expr(ExprNd), // never evaluated
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SizeofNd {
+    pub id: NodeId,
+    pub arg: SizeofArg,
+}
+
+impl SizeofNd {
+    pub fn new(arg: SizeofArg) -> Self {
+        SizeofNd {
+            id: NodeId::new(),
+            arg,
+        }
+    }
+}
+
+// Filled by analyzer for every item of the calc stack
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalcInfo {
+    pub ty: Type,
+    pub load: usize,  // cells to load from the address of an lvalue
+    pub scale: usize, // multiply for pointer arithmetic
+    pub div: usize,   // divide for pointer difference
+    pub off: usize,   // offset of struct member
+    pub trunc: usize, // bits kept when converted to a narrower type
+    pub drop: usize,  // cells dropped by a cast to void
+}
+
+impl CalcInfo {
+    pub fn new(ty: Type) -> Self {
+        CalcInfo {
+            ty,
+            load: 0,
+            scale: 1,
+            div: 1,
+            off: 0,
+            trunc: 0,
+            drop: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprNd {
+    pub id: NodeId,
     pub stack: Vec<CalcItem>,
 }
 
 impl ExprNd {
     pub fn new(stack: Vec<CalcItem>) -> Self {
-        ExprNd { stack: stack }
+        ExprNd {
+            id: NodeId::new(),
+            stack,
+        }
     }
 
     pub fn try_to_var(&self) -> Option<VarNd> {
@@ -30,16 +121,40 @@ impl ExprNd {
         }
     }
 
-    pub fn try_retrieve_const(&self) -> Option<i32> {
+    pub fn try_to_str(&self) -> Option<&str> {
+        match self.stack.as_slice() {
+            [CalcItem::Factor(FactorNd::Str(s))] => Some(s),
+            _ => None,
+        }
+    }
+
+    // `f` or `&f`, a function's address when the name is one
+    pub fn try_to_designator(&self) -> Option<&VarNd> {
+        match self.stack.as_slice() {
+            [CalcItem::Factor(FactorNd::Var(v))]
+            | [CalcItem::Factor(FactorNd::Var(v)), CalcItem::Op(Op::AddrOf)] => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn is_null_const(&self) -> bool {
+        self.try_retrieve_const(&HashMap::new(), &NodeTable::new()) == Some(0)
+    }
+
+    // consts: value of constant names by id, tbl: what the analyzer resolved so far
+    pub fn try_retrieve_const(&self, consts: &HashMap<u32, i32>, tbl: &NodeTable) -> Option<i32> {
         let mut st = Vec::new();
         for it in self.stack.iter() {
             match it {
+                CalcItem::Op(Op::Deref | Op::AddrOf | Op::Index) => {
+                    return None;
+                }
                 CalcItem::Op(op) => {
-                    let num = get_op_param_num(op.clone());
+                    let num = get_op_param_num(*op);
                     match num {
                         1 => {
                             let a = st.pop().unwrap();
-                            match calc_op_1(op.clone(), a) {
+                            match calc_op_1(*op, a) {
                                 Ok(n) => st.push(n),
                                 Err(_) => return None,
                             }
@@ -47,7 +162,7 @@ impl ExprNd {
                         2 => {
                             let b = st.pop().unwrap();
                             let a = st.pop().unwrap();
-                            match calc_op_2(op.clone(), a, b) {
+                            match calc_op_2(*op, a, b) {
                                 Ok(n) => st.push(n),
                                 Err(_) => return None,
                             }
@@ -57,9 +172,22 @@ impl ExprNd {
                         }
                     }
                 }
+                CalcItem::Cast(Type::Int) => {}
+                CalcItem::Cast(Type::Char) => {
+                    let a = st.pop().unwrap();
+                    st.push(calc_trunc(a, 8));
+                }
                 CalcItem::Factor(FactorNd::Value(Value::Int(num))) => {
                     st.push(*num);
                 }
+                CalcItem::Factor(FactorNd::Sizeof(n)) => match tbl.size(n.id) {
+                    0 => return None,
+                    sz => st.push(sz as i32),
+                },
+                CalcItem::Factor(FactorNd::Var(v)) => match consts.get(&tbl.symbol(v.id)) {
+                    Some(num) => st.push(*num),
+                    None => return None,
+                },
                 _ => {
                     return None;
                 }
@@ -74,208 +202,170 @@ impl ExprNd {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarNd {
+    pub id: NodeId,
     pub name: String,
-    id: RefCell<u32>,
 }
 
 impl VarNd {
     pub fn new(name: String) -> Self {
         VarNd {
-            name: name,
-            id: RefCell::new(0),
+            id: NodeId::new(),
+            name,
         }
     }
-
-    pub fn get_id(&self) -> u32 {
-        *self.id.borrow()
-    }
-
-    pub fn set_id(&self, id: u32) {
-        *self.id.borrow_mut() = id;
-    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssignNd {
-    pub var: VarNd,
+    pub lhs: ExprNd,
     pub expr: ExprNd,
 }
 
 impl AssignNd {
-    pub fn new(v: VarNd, ex: ExprNd) -> Self {
-        AssignNd { var: v, expr: ex }
+    pub fn new(lhs: ExprNd, ex: ExprNd) -> Self {
+        AssignNd { lhs, expr: ex }
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeclareNd {
+    pub storage: StorageClass,
     pub ty: Type,
+    pub bounds: Vec<Option<ExprNd>>, // array bounds left to the analyzer, outer first
     pub var: VarNd,
     pub expr: Option<ExprNd>,
+    pub list: Vec<ExprNd>, // items of a braced array initializer
 }
 
 impl DeclareNd {
-    pub fn new(ty: Type, v: VarNd, ex: Option<ExprNd>) -> Self {
+    pub fn new(
+        storage: StorageClass,
+        ty: Type,
+        bounds: Vec<Option<ExprNd>>,
+        v: VarNd,
+        ex: Option<ExprNd>,
+    ) -> Self {
         DeclareNd {
-            ty: ty,
+            storage,
+            ty,
+            bounds,
             var: v,
             expr: ex,
+            list: Vec::new(),
         }
     }
 
-    pub fn try_retrieve_const(&self) -> Option<i32> {
-        if self.expr.is_none() {
-            Some(0)
-        } else {
-            self.expr.as_ref().unwrap().try_retrieve_const()
+    pub fn try_retrieve_const(&self, consts: &HashMap<u32, i32>, tbl: &NodeTable) -> Option<i32> {
+        match &self.expr {
+            None => Some(0),
+            Some(ex) => ex.try_retrieve_const(consts, tbl),
         }
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IfNd {
     pub expr: ExprNd,
     pub item: ItemNd,
     pub els: Option<ElsNd>,
-    pub id: RefCell<u32>,
+    pub id: NodeId,
 }
 
 impl IfNd {
     pub fn new(expr: ExprNd, item: ItemNd, els: Option<ElsNd>) -> Self {
         IfNd {
-            expr: expr,
-            item: item,
-            els: els,
-            id: RefCell::new(0),
+            expr,
+            item,
+            els,
+            id: NodeId::new(),
         }
     }
-
-    pub fn get_id(&self) -> u32 {
-        *self.id.borrow()
-    }
-
-    pub fn set_id(&self, id: u32) {
-        *self.id.borrow_mut() = id;
-    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ElsNd {
     If(Box<IfNd>),
     Item(ItemNd),
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhileNd {
     pub expr: ExprNd,
     pub item: ItemNd,
-    pub id: RefCell<u32>,
+    pub id: NodeId,
 }
 
 impl WhileNd {
     pub fn new(expr: ExprNd, item: ItemNd) -> Self {
         WhileNd {
-            expr: expr,
-            item: item,
-            id: RefCell::new(0),
+            expr,
+            item,
+            id: NodeId::new(),
         }
     }
-
-    pub fn get_id(&self) -> u32 {
-        *self.id.borrow()
-    }
-
-    pub fn set_id(&self, id: u32) {
-        *self.id.borrow_mut() = id;
-    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BreakNd {
-    pub id: RefCell<u32>,
-    pub pop_off: RefCell<usize>,
+    pub id: NodeId,
 }
 
 impl BreakNd {
     pub fn new() -> Self {
-        BreakNd {
-            id: RefCell::new(0),
-            pop_off: RefCell::new(0),
-        }
-    }
-
-    pub fn get_id(&self) -> u32 {
-        *self.id.borrow()
-    }
-
-    pub fn set_id(&self, id: u32) {
-        *self.id.borrow_mut() = id;
-    }
-
-    pub fn get_pop_off(&self) -> usize {
-        *self.pop_off.borrow()
+        BreakNd { id: NodeId::new() }
     }
+}
 
-    pub fn set_pop_off(&self, pop_off: usize) {
-        *self.pop_off.borrow_mut() = pop_off;
+impl Default for BreakNd {
+    fn default() -> Self {
+        BreakNd::new()
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ContinueNd {
-    pub id: RefCell<u32>,
-    pub pop_off: RefCell<usize>,
+    pub id: NodeId,
 }
 
 impl ContinueNd {
     pub fn new() -> Self {
-        ContinueNd {
-            id: RefCell::new(0),
-            pop_off: RefCell::new(0),
-        }
-    }
-
-    pub fn get_id(&self) -> u32 {
-        *self.id.borrow()
-    }
-
-    pub fn set_id(&self, id: u32) {
-        *self.id.borrow_mut() = id;
-    }
-    pub fn get_pop_off(&self) -> usize {
-        *self.pop_off.borrow()
+        ContinueNd { id: NodeId::new() }
     }
+}
 
-    pub fn set_pop_off(&self, pop_off: usize) {
-        *self.pop_off.borrow_mut() = pop_off;
+impl Default for ContinueNd {
+    fn default() -> Self {
+        ContinueNd::new()
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnNd {
     pub expr: Option<ExprNd>,
-    pub sz: RefCell<usize>,
+    pub id: NodeId,
 }
 
 impl ReturnNd {
     pub fn new(expr: Option<ExprNd>) -> Self {
         ReturnNd {
-            expr: expr,
-            sz: RefCell::new(0),
+            expr,
+            id: NodeId::new(),
         }
     }
-
-    pub fn get_sz(&self) -> usize {
-        *self.sz.borrow()
-    }
-
-    pub fn set_sz(&self, sz: usize) {
-        *self.sz.borrow_mut() = sz;
-    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StmtNd {
     Assign(AssignNd),
     Declare(DeclareNd),
@@ -285,6 +375,7 @@ pub enum StmtNd {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ItemNd {
     Stmt(StmtNd),
     Block(BlockNd),
@@ -297,42 +388,33 @@ pub enum ItemNd {
 
 impl ItemNd {
     pub fn is_declare(&self) -> bool {
-        if let ItemNd::Stmt(StmtNd::Declare(_)) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, ItemNd::Stmt(StmtNd::Declare(_)))
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockNd {
     pub items: Vec<ItemNd>,
-    pub id: RefCell<u32>,
+    pub id: NodeId,
 }
 
 impl BlockNd {
     pub fn new(items: Vec<ItemNd>) -> Self {
         BlockNd {
-            items: items,
-            id: RefCell::new(0),
+            items,
+            id: NodeId::new(),
         }
     }
-
-    pub fn get_id(&self) -> u32 {
-        *self.id.borrow()
-    }
-
-    pub fn set_id(&self, id: u32) {
-        *self.id.borrow_mut() = id;
-    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncNd {
     pub ret_ty: Type,
     pub var: VarNd,
     pub params: Vec<(Type, Option<VarNd>)>,
+    pub variadic: bool,
     pub block: Option<BlockNd>,
 }
 
@@ -341,13 +423,15 @@ impl FuncNd {
         ty: Type,
         var: VarNd,
         params: Vec<(Type, Option<VarNd>)>,
+        variadic: bool,
         block: Option<BlockNd>,
     ) -> Self {
         FuncNd {
             ret_ty: ty,
-            var: var,
-            params: params,
-            block: block,
+            var,
+            params,
+            variadic,
+            block,
         }
     }
 
@@ -363,20 +447,22 @@ impl FuncNd {
                 }
             }
         }
-        return true;
+        true
     }
 
+    // top level const of params and return is not part of the type
     pub fn func_ty(&self) -> Type {
         let mut ty_vec = Vec::new();
         for (t, _) in self.params.iter() {
-            ty_vec.push(t.clone());
+            ty_vec.push(t.unqualified().clone());
         }
-        ty_vec.push(self.ret_ty.clone());
-        Type::Func(ty_vec)
+        ty_vec.push(self.ret_ty.unqualified().clone());
+        Type::Func(ty_vec, self.variadic)
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncCallNd {
     pub var: VarNd,
     pub params: Vec<ExprNd>,
@@ -384,26 +470,96 @@ pub struct FuncCallNd {
 
 impl FuncCallNd {
     pub fn new(var: VarNd, params: Vec<ExprNd>) -> Self {
-        FuncCallNd {
-            var: var,
-            params: params,
-        }
+        FuncCallNd { var, params }
     }
 }
 
+// call of a function pointer given by an expression, e.g. (*fp)(x), ops[i](x)
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndirectCallNd {
+    pub callee: ExprNd,
+    pub params: Vec<ExprNd>,
+}
+
+impl IndirectCallNd {
+    pub fn new(callee: ExprNd, params: Vec<ExprNd>) -> Self {
+        IndirectCallNd { callee, params }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StructNd {
+    pub name: String,
+    pub fields: Vec<DeclareNd>,
+}
+
+impl StructNd {
+    pub fn new(name: String, fields: Vec<DeclareNd>) -> Self {
+        StructNd { name, fields }
+    }
+
+    // tag of `typedef struct {...} name;`, braces keep it apart from user tags
+    pub fn anonymous_tag(typedef: &str) -> String {
+        format!("{{{}}}", typedef)
+    }
+
+    // the typedef name behind an anonymous tag
+    pub fn typedef_of(tag: &str) -> Option<&str> {
+        tag.strip_prefix('{')?.strip_suffix('}')
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnumNd {
+    pub name: Option<String>,
+    pub items: Vec<(VarNd, Option<ExprNd>)>,
+}
+
+impl EnumNd {
+    pub fn new(name: Option<String>, items: Vec<(VarNd, Option<ExprNd>)>) -> Self {
+        EnumNd { name, items }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypedefNd {
+    pub ty: Type,
+    pub name: String,
+    pub def: Option<StructNd>, // typedef struct {...} name;
+}
+
+impl TypedefNd {
+    pub fn new(ty: Type, name: String, def: Option<StructNd>) -> Self {
+        TypedefNd { ty, name, def }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GItemNd {
     Declare(DeclareNd),
     Func(FuncNd),
+    Struct(StructNd),
+    Enum(EnumNd),
+    Typedef(TypedefNd),
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RootNd {
+    pub id: NodeId,
     pub items: Vec<GItemNd>,
 }
 
 impl RootNd {
     pub fn new(items: Vec<GItemNd>) -> Self {
-        RootNd { items: items }
+        RootNd {
+            id: NodeId::new(),
+            items,
+        }
     }
 }
@@ -1,18 +1,22 @@
 use super::super::parser::Parser;
 use super::err::ErrKind;
-use super::nodes::FactorNd;
+use super::nodes::{ExprNd, FactorNd, IndirectCallNd, SizeofArg, SizeofNd};
 use super::seq::{SeqPack, Sequence};
-use super::token::Token;
+use super::token::{Token, Type};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 use std::collections::HashMap;
 
 #[derive(Debug, EnumIter, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Op {
     Paren,
+    Index,
     UnaryMinus,
     Not,
+    Deref,
+    AddrOf,
     Multiply,
     Divide,
     Modulo,
@@ -29,9 +33,10 @@ pub enum Op {
 }
 
 // op (param num, num of ops)
-const LEVEL_OPS: [(u8, u8); 8] = [
+const LEVEL_OPS: [(u8, u8); 9] = [
     (1, 1),
-    (1, 2),
+    (2, 1),
+    (1, 4),
     (2, 3),
     (2, 2),
     (2, 4),
@@ -40,6 +45,8 @@ const LEVEL_OPS: [(u8, u8); 8] = [
     (2, 1),
 ];
 
+const POSTFIX_LEVEL: u8 = 1;
+
 fn get_level(op: Op) -> u8 {
     let mut off = 0;
     for (idx, o) in Op::iter().enumerate() {
@@ -83,17 +90,31 @@ fn bin_op_tokens(op: Op) -> Vec<Token> {
     }
 }
 
+fn unary_op_token(op: Op) -> Token {
+    match op {
+        Op::UnaryMinus => Token::Minus,
+        Op::Not => Token::Not,
+        Op::Deref => Token::Multiply,
+        Op::AddrOf => Token::And,
+        _ => {
+            panic!("not unary op: {:?}", op);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CalcItem {
     Op(Op),
     Factor(FactorNd),
+    Member(String),
+    Cast(Type),
 }
 
-fn eat_op(
-    seq: Sequence,
-    op: Op,
-    mp: &mut HashMap<(u8, u8), SeqPack<Vec<CalcItem>>>,
-) -> SeqPack<Vec<CalcItem>> {
+// keyed by (tokens left, level)
+type CalcMemo = HashMap<(usize, u8), SeqPack<Vec<CalcItem>>>;
+
+fn eat_op(seq: Sequence, op: Op, mp: &mut CalcMemo) -> SeqPack<Vec<CalcItem>> {
     let level = get_level(op);
     match op {
         Op::Paren => {
@@ -102,69 +123,160 @@ fn eat_op(
             let (seq, _) = seq.eat(Token::RParen)?;
             Some((seq, st))
         }
-        Op::UnaryMinus => {
-            let (seq, _) = seq.eat(Token::Minus)?;
-            let (seq, mut st) = _get_calc_stack(seq, level - 1, mp)?;
-            st.push(CalcItem::Op(Op::UnaryMinus));
+        Op::Index => {
+            let (seq, _) = seq.eat(Token::LBracket)?;
+            let (seq, mut st) = _get_calc_stack(seq, max_level(), mp)?;
+            let (seq, _) = seq.eat(Token::RBracket)?;
+            st.push(CalcItem::Op(Op::Index));
             Some((seq, st))
         }
-        Op::Not => {
-            let (seq, _) = seq.eat(Token::Not)?;
+        Op::UnaryMinus | Op::Not | Op::Deref | Op::AddrOf => {
+            let (seq, _) = seq.eat(unary_op_token(op))?;
             let (seq, mut st) = _get_calc_stack(seq, level, mp)?;
-            st.push(CalcItem::Op(Op::Not));
+            st.push(CalcItem::Op(op));
             Some((seq, st))
         }
-        Op::Multiply
-        | Op::Divide
-        | Op::Modulo
-        | Op::Add
-        | Op::Minus
-        | Op::GreaterEq
-        | Op::GreaterThan
-        | Op::LessEq
-        | Op::LessThan
-        | Op::Eq
-        | Op::NotEq
-        | Op::And
-        | Op::Or => {
-            let mut stack = Vec::new();
-            let (seq, st) = _get_calc_stack(seq, level - 1, mp)?;
-            stack.extend(st);
+        _ => {
             let (seq, _) = seq.eats(&bin_op_tokens(op))?;
-            let (seq, st) = _get_calc_stack(seq, level, mp)?;
+            let (seq, mut st) = _get_calc_stack(seq, level - 1, mp)?;
+            st.push(CalcItem::Op(op));
+            Some((seq, st))
+        }
+    }
+}
+
+// (type)expr, at the level of unary ops
+fn eat_cast(seq: Sequence, level: u8, mp: &mut CalcMemo) -> SeqPack<Vec<CalcItem>> {
+    let (seq, _) = seq.eat(Token::LParen)?;
+    let (seq, ty) = seq.eat_type_name()?;
+    let (seq, _) = seq.eat(Token::RParen)?;
+    let (seq, mut st) = _get_calc_stack(seq, level, mp)?;
+    st.push(CalcItem::Cast(ty));
+    Some((seq, st))
+}
+
+// sizeof(type) or sizeof expr, the operand is not evaluated
+fn eat_sizeof(seq: Sequence, level: u8, mp: &mut CalcMemo) -> SeqPack<Vec<CalcItem>> {
+    let (seq, _) = seq.eat(Token::Sizeof)?;
+    let ty = seq.eat(Token::LParen).and_then(|(s, _)| s.eat_type_name());
+    let (seq, arg) = match ty {
+        Some((s, ty)) => {
+            let (s, _) = s.eat(Token::RParen)?;
+            (s, SizeofArg::Type(ty))
+        }
+        None => {
+            let (s, st) = _get_calc_stack(seq, level, mp)?;
+            (s, SizeofArg::Expr(ExprNd::new(st)))
+        }
+    };
+    let nd = FactorNd::Sizeof(SizeofNd::new(arg));
+    Some((seq, vec![CalcItem::Factor(nd)]))
+}
+
+// p->name is (*p).name
+fn eat_member(seq: Sequence) -> SeqPack<Vec<CalcItem>> {
+    if let Some((seq, _)) = seq.eat(Token::Dot) {
+        let (seq, name) = seq.eat_name()?;
+        return Some((seq, vec![CalcItem::Member(name)]));
+    }
+    let (seq, _) = seq.eats(&[Token::Minus, Token::Greater])?;
+    let (seq, name) = seq.eat_name()?;
+    Some((seq, vec![CalcItem::Op(Op::Deref), CalcItem::Member(name)]))
+}
+
+// (expr, ...) of a call
+pub fn eat_call_args(seq: Sequence) -> SeqPack<Vec<ExprNd>> {
+    let mut params = Vec::new();
+    let (mut mseq, _) = seq.eat(Token::LParen)?;
+    while mseq.get(0) != Some(Token::RParen) {
+        if !params.is_empty() {
+            let (seq, _) = mseq.eat(Token::Comma)?;
+            mseq = seq;
+        }
+        let (seq, ex) = ExprNd::parse(mseq)?;
+        params.push(ex);
+        mseq = seq;
+    }
+    Some((mseq.advance(1), params))
+}
+
+// postfix ops chain after a primary, e.g. a[1].b->c, ops[i](x)
+fn eat_postfix(seq: Sequence, mp: &mut CalcMemo) -> SeqPack<Vec<CalcItem>> {
+    let (mut seq, mut stack) = _get_calc_stack(seq, POSTFIX_LEVEL - 1, mp)?;
+    loop {
+        if let Some((s, st)) = eat_op(seq.clone(), Op::Index, mp) {
             stack.extend(st);
-            stack.push(CalcItem::Op(op));
-            Some((seq, stack))
+            seq = s;
+        } else if let Some((s, st)) = eat_member(seq.clone()) {
+            stack.extend(st);
+            seq = s;
+        } else if let Some((s, params)) = eat_call_args(seq.clone()) {
+            // calls through a function pointer take the operand so far as callee
+            let nd = IndirectCallNd::new(ExprNd::new(stack), params);
+            stack = vec![CalcItem::Factor(FactorNd::Indirect(nd))];
+            seq = s;
+        } else {
+            break;
         }
     }
+    Some((seq, stack))
 }
 
-fn _get_calc_stack(
-    seq: Sequence,
-    level: u8,
-    mp: &mut HashMap<(u8, u8), SeqPack<Vec<CalcItem>>>,
-) -> SeqPack<Vec<CalcItem>> {
-    let ky = (seq.len() as u8, level);
+// binary ops of the same level are left associative
+fn eat_binary(seq: Sequence, level: u8, mp: &mut CalcMemo) -> SeqPack<Vec<CalcItem>> {
+    let (mut seq, mut stack) = _get_calc_stack(seq, level - 1, mp)?;
+    'outer: loop {
+        for op in Op::iter() {
+            if get_level(op) != level {
+                continue;
+            }
+            if let Some((s, st)) = eat_op(seq.clone(), op, mp) {
+                stack.extend(st);
+                seq = s;
+                continue 'outer;
+            }
+        }
+        break;
+    }
+    Some((seq, stack))
+}
+
+fn _get_calc_stack(seq: Sequence, level: u8, mp: &mut CalcMemo) -> SeqPack<Vec<CalcItem>> {
+    let ky = (seq.len(), level);
     if mp.contains_key(&ky) {
         return mp[&ky].clone();
     }
     mp.insert(ky, None);
-    for op in Op::iter() {
-        if get_level(op) != level {
-            continue;
+    let res = if level == 0 {
+        match eat_op(seq.clone(), Op::Paren, mp) {
+            Some(res) => Some(res),
+            None => {
+                let (seq, factor) = FactorNd::parse(seq)?;
+                Some((seq, vec![CalcItem::Factor(factor)]))
+            }
         }
-        if let Some((seq, st)) = eat_op(seq.clone(), op, mp) {
-            let res = Some((seq, st));
-            mp.insert(ky, res.clone());
-            return res;
+    } else if level == POSTFIX_LEVEL {
+        eat_postfix(seq, mp)
+    } else if LEVEL_OPS[level as usize].0 == 1 {
+        let mut res = eat_cast(seq.clone(), level, mp);
+        if res.is_none() {
+            res = eat_sizeof(seq.clone(), level, mp);
+        }
+        for op in Op::iter() {
+            if get_level(op) != level {
+                continue;
+            }
+            if res.is_some() {
+                break;
+            }
+            res = eat_op(seq.clone(), op, mp);
+        }
+        match res {
+            Some(r) => Some(r),
+            None => _get_calc_stack(seq, level - 1, mp),
         }
-    }
-
-    let res = if level == 0 {
-        let (seq, factor) = FactorNd::parse(seq)?;
-        Some((seq, vec![CalcItem::Factor(factor)]))
     } else {
-        _get_calc_stack(seq, level - 1, mp)
+        eat_binary(seq, level, mp)
     };
     mp.insert(ky, res.clone());
     res
@@ -176,12 +288,23 @@ pub fn get_calc_stack(seq: Sequence) -> SeqPack<Vec<CalcItem>> {
     _get_calc_stack(seq, max_level(), &mut mp)
 }
 
+// 0 binds tightest, binary ops are left associative
+pub fn get_op_level(op: Op) -> u8 {
+    get_level(op)
+}
+
 #[allow(dead_code)]
 pub fn get_op_param_num(op: Op) -> usize {
     let level = get_level(op);
     LEVEL_OPS[level as usize].0 as usize
 }
 
+// keep the low bits, sign extended
+pub fn calc_trunc(a: i32, bits: usize) -> i32 {
+    let sh = 32 - bits as u32;
+    (a << sh) >> sh
+}
+
 pub fn calc_op_1(op: Op, a: i32) -> Result<i32, ErrKind> {
     match op {
         Op::Paren => Ok(a),
@@ -193,9 +316,7 @@ pub fn calc_op_1(op: Op, a: i32) -> Result<i32, ErrKind> {
                 Ok(1)
             }
         }
-        _ => {
-            panic!("{:?} is not op_1", op);
-        }
+        _ => Err(ErrKind::InvalidCode),
     }
 }
 
@@ -274,8 +395,6 @@ pub fn calc_op_2(op: Op, a: i32, b: i32) -> Result<i32, ErrKind> {
                 Ok(0)
             }
         }
-        _ => {
-            panic!("{:?} is not op_2", op);
-        }
+        _ => Err(ErrKind::InvalidCode),
     }
 }
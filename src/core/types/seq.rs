@@ -1,9 +1,13 @@
 use super::token::{Token, Type, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Sequence {
     tokens: Rc<Vec<Token>>,
+    typedefs: Rc<RefCell<HashMap<String, Type>>>, // shared by all cursors
+    furthest: Rc<Cell<usize>>,                    // where a failed parse got stuck
     cur: usize,
 }
 
@@ -14,10 +18,23 @@ impl Sequence {
     pub fn new(tks: Vec<Token>) -> Self {
         Sequence {
             tokens: Rc::new(tks),
+            typedefs: Rc::new(RefCell::new(HashMap::new())),
+            furthest: Rc::new(Cell::new(0)),
             cur: 0,
         }
     }
 
+    // typedef names declared before these tokens
+    pub fn with_typedefs(tks: Vec<Token>, typedefs: HashMap<String, Type>) -> Self {
+        let seq = Sequence::new(tks);
+        *seq.typedefs.borrow_mut() = typedefs;
+        seq
+    }
+
+    pub fn typedefs(&self) -> HashMap<String, Type> {
+        self.typedefs.borrow().clone()
+    }
+
     pub fn eat(&self, t: Token) -> SeqPack<Token> {
         if self.get(0) == Some(t.clone()) {
             Some((self.advance(1), t))
@@ -43,14 +60,136 @@ impl Sequence {
         }
     }
 
-    pub fn eat_type(&self) -> SeqPack<Type> {
-        if let Some(Token::Type(v)) = self.get(0) {
-            Some((self.advance(1), v))
+    pub fn eat_str(&self) -> SeqPack<String> {
+        if let Some(Token::Str(s)) = self.get(0) {
+            Some((self.advance(1), s))
         } else {
             None
         }
     }
 
+    pub fn eat_const(&self) -> (Sequence, bool) {
+        match self.eat(Token::Const) {
+            Some((seq, _)) => (seq, true),
+            None => (self.clone(), false),
+        }
+    }
+
+    // const may come before or after the base type
+    pub fn eat_type(&self) -> SeqPack<Type> {
+        let (seq, lc) = self.eat_const();
+        let (seq, ty) = seq.eat_base_type()?;
+        let (seq, rc) = seq.eat_const();
+        if lc || rc {
+            Some((seq, ty.into_const()))
+        } else {
+            Some((seq, ty))
+        }
+    }
+
+    fn eat_base_type(&self) -> SeqPack<Type> {
+        match self.get(0) {
+            Some(Token::Type(v)) => Some((self.advance(1), v)),
+            Some(Token::Struct) => {
+                let (seq, name) = self.advance(1).eat_name()?;
+                Some((seq, Type::Struct(name)))
+            }
+            Some(Token::Enum) => {
+                let (seq, _) = self.advance(1).eat_name()?;
+                Some((seq, Type::Int))
+            }
+            // points to the next variadic arg
+            Some(Token::VaList) => Some((self.advance(1), Type::Ptr(Box::new(Type::Void)))),
+            Some(Token::Name(s)) => {
+                let ty = self.typedefs.borrow().get(&s)?.clone();
+                Some((self.advance(1), ty))
+            }
+            _ => None,
+        }
+    }
+
+    // *, * const ...
+    pub fn eat_pointers(&self, ty: Type) -> (Sequence, Type) {
+        let mut mseq = self.clone();
+        let mut ty = ty;
+        while let Some((seq, _)) = mseq.eat(Token::Multiply) {
+            ty = Type::Ptr(Box::new(ty));
+            let (seq, c) = seq.eat_const();
+            if c {
+                ty = ty.into_const();
+            }
+            mseq = seq;
+        }
+        (mseq, ty)
+    }
+
+    // (*name)(params) after the return type, the name is optional
+    pub fn eat_func_ptr(&self, ret: Type) -> SeqPack<(Type, Option<String>)> {
+        let (seq, _) = self.eats(&[Token::LParen, Token::Multiply])?;
+        let (seq, name) = match seq.eat_name() {
+            Some((seq, name)) => (seq, Some(name)),
+            None => (seq, None),
+        };
+        let (seq, _) = seq.eat(Token::RParen)?;
+        let (seq, ty) = seq.eat_func_params(ret)?;
+        Some((seq, (Type::Ptr(Box::new(ty)), name)))
+    }
+
+    // ... after the last param
+    pub fn eat_ellipsis(&self) -> SeqPack<()> {
+        self.eats(&[Token::Dot, Token::Dot, Token::Dot])
+    }
+
+    // (type [name], ...) of a function pointer, names are dropped
+    pub fn eat_func_params(&self, ret: Type) -> SeqPack<Type> {
+        let (mut mseq, _) = self.eat(Token::LParen)?;
+        let mut tys = Vec::new();
+        let mut variadic = false;
+        while mseq.get(0) != Some(Token::RParen) {
+            if variadic {
+                return None;
+            }
+            if !tys.is_empty() {
+                let (seq, _) = mseq.eat(Token::Comma)?;
+                mseq = seq;
+                if let Some((seq, _)) = mseq.eat_ellipsis() {
+                    variadic = true;
+                    mseq = seq;
+                    continue;
+                }
+            }
+            let (seq, ty) = mseq.eat_type()?;
+            let (seq, ty) = seq.eat_pointers(ty);
+            let (seq, ty) = match seq.eat_func_ptr(ty.clone()) {
+                Some((seq, (ty, _))) => (seq, ty),
+                None => match seq.eat_name() {
+                    Some((seq, _)) => (seq, ty),
+                    None => (seq, ty),
+                },
+            };
+            tys.push(ty.unqualified().clone());
+            mseq = seq;
+        }
+        tys.push(ret.unqualified().clone());
+        Some((mseq.advance(1), Type::Func(tys, variadic)))
+    }
+
+    // type of casts and sizeof, e.g. int *, int (*)(int)
+    pub fn eat_type_name(&self) -> SeqPack<Type> {
+        let (seq, ty) = self.eat_type()?;
+        let (seq, ty) = seq.eat_pointers(ty);
+        match seq.eat_func_ptr(ty.clone()) {
+            Some((seq, (ty, None))) => Some((seq, ty)),
+            Some(_) => None,
+            None => Some((seq, ty)),
+        }
+    }
+
+    // only called once the typedef item is accepted, see RootNd::parse
+    pub fn add_typedef(&self, name: &str, ty: Type) {
+        self.typedefs.borrow_mut().insert(name.to_owned(), ty);
+    }
+
     pub fn eat_name(&self) -> SeqPack<String> {
         if let Some(Token::Name(s)) = self.get(0) {
             Some((self.advance(1), s))
@@ -94,12 +233,19 @@ impl Sequence {
         if cur > self.tokens.len() {
             cur = self.tokens.len();
         }
+        self.furthest.set(self.furthest.get().max(cur));
         Sequence {
             tokens: self.tokens.clone(),
-            cur: cur,
+            typedefs: self.typedefs.clone(),
+            furthest: self.furthest.clone(),
+            cur,
         }
     }
 
+    pub fn furthest(&self) -> usize {
+        self.furthest.get()
+    }
+
     pub fn len(&self) -> usize {
         self.tokens.len() - self.cur
     }
@@ -0,0 +1,89 @@
+use super::nodes::{CalcInfo, NodeId};
+use super::token::Type;
+use std::collections::HashMap;
+
+// What the analyzer resolves for nodes, the tree itself is never mutated
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeTable {
+    symbols: HashMap<NodeId, u32>,    // var, func or const of a name
+    scopes: HashMap<NodeId, u32>,     // scope of a block, if, loop or the loop of a jump
+    pop_offs: HashMap<NodeId, usize>, // cells popped by break and continue
+    ret_sizes: HashMap<NodeId, usize>,
+    infos: HashMap<NodeId, Vec<CalcInfo>>, // per item of an expr's calc stack
+    sizes: HashMap<NodeId, usize>,         // sizeof results
+}
+
+impl NodeTable {
+    pub fn new() -> Self {
+        NodeTable::default()
+    }
+
+    pub fn set_symbol(&mut self, nd: NodeId, id: u32) {
+        self.symbols.insert(nd, id);
+    }
+
+    // 0 until resolved
+    pub fn symbol(&self, nd: NodeId) -> u32 {
+        self.symbols.get(&nd).copied().unwrap_or(0)
+    }
+
+    pub fn set_scope(&mut self, nd: NodeId, id: u32) {
+        self.scopes.insert(nd, id);
+    }
+
+    pub fn scope(&self, nd: NodeId) -> u32 {
+        self.scopes.get(&nd).copied().unwrap_or(0)
+    }
+
+    pub fn set_pop_off(&mut self, nd: NodeId, off: usize) {
+        self.pop_offs.insert(nd, off);
+    }
+
+    pub fn pop_off(&self, nd: NodeId) -> usize {
+        self.pop_offs.get(&nd).copied().unwrap_or(0)
+    }
+
+    pub fn set_ret_size(&mut self, nd: NodeId, sz: usize) {
+        self.ret_sizes.insert(nd, sz);
+    }
+
+    pub fn ret_size(&self, nd: NodeId) -> usize {
+        self.ret_sizes.get(&nd).copied().unwrap_or(0)
+    }
+
+    pub fn set_infos(&mut self, nd: NodeId, infos: Vec<CalcInfo>) {
+        self.infos.insert(nd, infos);
+    }
+
+    pub fn infos(&self, nd: NodeId) -> &[CalcInfo] {
+        match self.infos.get(&nd) {
+            Some(infos) => infos,
+            None => &[],
+        }
+    }
+
+    // implicit conversion of the result
+    pub fn set_trunc(&mut self, nd: NodeId, bits: usize) {
+        if let Some(info) = self.infos.get_mut(&nd).and_then(|v| v.last_mut()) {
+            info.trunc = bits;
+        }
+    }
+
+    // type of an analyzed expr
+    pub fn expr_type(&self, nd: NodeId) -> Type {
+        match self.infos(nd).last() {
+            Some(info) => info.ty.clone(),
+            None => Type::Void,
+        }
+    }
+
+    pub fn set_size(&mut self, nd: NodeId, sz: usize) {
+        self.sizes.insert(nd, sz);
+    }
+
+    // 0 until analyzed
+    pub fn size(&self, nd: NodeId) -> usize {
+        self.sizes.get(&nd).copied().unwrap_or(0)
+    }
+}
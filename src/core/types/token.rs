@@ -1,23 +1,66 @@
+use super::infos::StructInfo;
+use std::collections::HashMap;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     Void,
     Int(i32),
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Void,
     Int,
-    Func(Vec<Type>),
+    Char,
+    Ptr(Box<Type>),
+    Array(Box<Type>, usize),
+    Struct(String),
+    Func(Vec<Type>, bool), // params then return, variadic
+    Const(Box<Type>),
+}
+
+impl Type {
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Type::Int | Type::Char | Type::Ptr(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int | Type::Char)
+    }
+
+    pub fn is_ptr(&self) -> bool {
+        matches!(self, Type::Ptr(_))
+    }
+
+    pub fn is_const(&self) -> bool {
+        matches!(self, Type::Const(_))
+    }
+
+    // const of an array qualifies its elements
+    pub fn into_const(self) -> Type {
+        match self {
+            Type::Const(_) => self,
+            Type::Array(t, n) => Type::Array(Box::new(t.into_const()), n),
+            t => Type::Const(Box::new(t)),
+        }
+    }
+
+    pub fn unqualified(&self) -> &Type {
+        match self {
+            Type::Const(t) => t,
+            t => t,
+        }
+    }
 }
 
 #[allow(unused)]
 pub fn match_value_type(v: Value, t: Type) -> bool {
-    match (t, v) {
-        (Type::Int, Value::Int(_)) => true,
-        (Type::Void, Value::Void) => true,
-        _ => false,
-    }
+    matches!(
+        (t, v),
+        (Type::Int, Value::Int(_)) | (Type::Void, Value::Void)
+    )
 }
 
 pub fn get_value_type(v: Value) -> Type {
@@ -36,14 +79,22 @@ pub fn get_default_value(ty: Type) -> Value {
     }
 }
 
-pub fn get_type_size(ty: Type) -> usize {
+// size in cells, 0 for void and undefined structs
+pub fn get_type_size(ty: Type, structs: &HashMap<String, StructInfo>) -> usize {
     match ty {
-        Type::Int => 1,
+        Type::Int | Type::Char | Type::Ptr(_) => 1,
+        Type::Array(t, n) => get_type_size(*t, structs) * n,
+        Type::Const(t) => get_type_size(*t, structs),
+        Type::Struct(name) => match structs.get(&name) {
+            Some(info) => info.size,
+            None => 0,
+        },
         _ => 0,
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
     // Single
     Add,
@@ -60,6 +111,7 @@ pub enum Token {
     LBrace,
     RBrace,
     Comma,
+    Dot,
 
     Or,
     And,
@@ -75,14 +127,26 @@ pub enum Token {
     Return,
     Continue,
     Break,
+    Struct,
+    Enum,
+    Typedef,
+    Const,
+    Static,
+    Extern,
+    Sizeof,
+    VaList,
+    VaStart,
+    VaArg,
+    VaEnd,
 
     // Special
     Type(Type),
     Value(Value),
+    Str(String),
     Name(String),
 }
 
-const RESERVED_SINGLE_CHAR_TOKENS: [(char, Token); 19] = [
+const RESERVED_SINGLE_CHAR_TOKENS: [(char, Token); 20] = [
     ('+', Token::Add),
     ('-', Token::Minus),
     ('*', Token::Multiply),
@@ -97,6 +161,7 @@ const RESERVED_SINGLE_CHAR_TOKENS: [(char, Token); 19] = [
     ('}', Token::RBrace),
     (';', Token::Semicolon),
     (',', Token::Comma),
+    ('.', Token::Dot),
     ('&', Token::And),
     ('|', Token::Or),
     ('!', Token::Not),
@@ -104,15 +169,27 @@ const RESERVED_SINGLE_CHAR_TOKENS: [(char, Token); 19] = [
     ('>', Token::Greater),
 ];
 
-const RESERVED_KEYWORDS: [(&'static str, Token); 8] = [
+const RESERVED_KEYWORDS: [(&str, Token); 20] = [
     ("if", Token::If),
     ("else", Token::Else),
     ("while", Token::While),
     ("return", Token::Return),
     ("break", Token::Break),
     ("continue", Token::Continue),
+    ("struct", Token::Struct),
+    ("enum", Token::Enum),
+    ("typedef", Token::Typedef),
+    ("const", Token::Const),
+    ("static", Token::Static),
+    ("extern", Token::Extern),
+    ("sizeof", Token::Sizeof),
+    ("va_list", Token::VaList),
+    ("va_start", Token::VaStart),
+    ("va_arg", Token::VaArg),
+    ("va_end", Token::VaEnd),
     ("void", Token::Type(Type::Void)),
     ("int", Token::Type(Type::Int)),
+    ("char", Token::Type(Type::Char)),
 ];
 
 // Only positive & No overflow
@@ -122,7 +199,7 @@ fn get_num(word: &str) -> Option<Token> {
         if !c.is_ascii_digit() {
             return None;
         }
-        let d = (c as u8) - ('0' as u8);
+        let d = (c as u8) - b'0';
         num = num * 10 + d as i32;
     }
     Some(Token::Value(Value::Int(num)))
@@ -150,10 +227,8 @@ pub fn get_token_from_word(word: &str) -> Option<Token> {
     }
     if let Some(t) = get_num(word) {
         Some(t)
-    } else if let Some(t) = get_name(word) {
-        Some(t)
     } else {
-        None
+        get_name(word)
     }
 }
 
@@ -165,3 +240,23 @@ pub fn get_token_from_char(ch: char) -> Option<Token> {
     }
     None
 }
+
+// source text of a token, for messages
+pub fn get_token_text(t: &Token) -> String {
+    for it in RESERVED_SINGLE_CHAR_TOKENS.iter() {
+        if it.1 == *t {
+            return it.0.to_string();
+        }
+    }
+    for it in RESERVED_KEYWORDS.iter() {
+        if it.1 == *t {
+            return it.0.to_string();
+        }
+    }
+    match t {
+        Token::Value(Value::Int(n)) => n.to_string(),
+        Token::Str(s) => format!("{:?}", s),
+        Token::Name(s) => s.clone(),
+        _ => format!("{:?}", t),
+    }
+}
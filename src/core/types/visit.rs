@@ -0,0 +1,530 @@
+use super::nodes::*;
+use super::op::CalcItem;
+
+// Read only walk over the tree, override the nodes a pass cares about
+// and call the walk_* function to keep descending into children
+pub trait Visitor {
+    fn visit_root(&mut self, n: &RootNd) {
+        walk_root(self, n);
+    }
+
+    fn visit_gitem(&mut self, n: &GItemNd) {
+        walk_gitem(self, n);
+    }
+
+    fn visit_func(&mut self, n: &FuncNd) {
+        walk_func(self, n);
+    }
+
+    fn visit_struct(&mut self, n: &StructNd) {
+        walk_struct(self, n);
+    }
+
+    fn visit_enum(&mut self, n: &EnumNd) {
+        walk_enum(self, n);
+    }
+
+    fn visit_typedef(&mut self, n: &TypedefNd) {
+        walk_typedef(self, n);
+    }
+
+    fn visit_declare(&mut self, n: &DeclareNd) {
+        walk_declare(self, n);
+    }
+
+    fn visit_block(&mut self, n: &BlockNd) {
+        walk_block(self, n);
+    }
+
+    fn visit_item(&mut self, n: &ItemNd) {
+        walk_item(self, n);
+    }
+
+    fn visit_stmt(&mut self, n: &StmtNd) {
+        walk_stmt(self, n);
+    }
+
+    fn visit_assign(&mut self, n: &AssignNd) {
+        walk_assign(self, n);
+    }
+
+    fn visit_if(&mut self, n: &IfNd) {
+        walk_if(self, n);
+    }
+
+    fn visit_els(&mut self, n: &ElsNd) {
+        walk_els(self, n);
+    }
+
+    fn visit_while(&mut self, n: &WhileNd) {
+        walk_while(self, n);
+    }
+
+    fn visit_return(&mut self, n: &ReturnNd) {
+        walk_return(self, n);
+    }
+
+    fn visit_break(&mut self, _n: &BreakNd) {}
+
+    fn visit_continue(&mut self, _n: &ContinueNd) {}
+
+    fn visit_expr(&mut self, n: &ExprNd) {
+        walk_expr(self, n);
+    }
+
+    fn visit_factor(&mut self, n: &FactorNd) {
+        walk_factor(self, n);
+    }
+
+    fn visit_var(&mut self, _n: &VarNd) {}
+
+    fn visit_func_call(&mut self, n: &FuncCallNd) {
+        walk_func_call(self, n);
+    }
+
+    fn visit_indirect_call(&mut self, n: &IndirectCallNd) {
+        walk_indirect_call(self, n);
+    }
+
+    fn visit_sizeof(&mut self, n: &SizeofNd) {
+        walk_sizeof(self, n);
+    }
+
+    fn visit_va(&mut self, n: &VaNd) {
+        walk_va(self, n);
+    }
+}
+
+pub fn walk_root<V: Visitor + ?Sized>(v: &mut V, n: &RootNd) {
+    for it in n.items.iter() {
+        v.visit_gitem(it);
+    }
+}
+
+pub fn walk_gitem<V: Visitor + ?Sized>(v: &mut V, n: &GItemNd) {
+    match n {
+        GItemNd::Declare(n) => v.visit_declare(n),
+        GItemNd::Func(n) => v.visit_func(n),
+        GItemNd::Struct(n) => v.visit_struct(n),
+        GItemNd::Enum(n) => v.visit_enum(n),
+        GItemNd::Typedef(n) => v.visit_typedef(n),
+    }
+}
+
+pub fn walk_func<V: Visitor + ?Sized>(v: &mut V, n: &FuncNd) {
+    v.visit_var(&n.var);
+    for (_, p) in n.params.iter() {
+        if let Some(p) = p {
+            v.visit_var(p);
+        }
+    }
+    if let Some(b) = &n.block {
+        v.visit_block(b);
+    }
+}
+
+pub fn walk_struct<V: Visitor + ?Sized>(v: &mut V, n: &StructNd) {
+    for f in n.fields.iter() {
+        v.visit_declare(f);
+    }
+}
+
+pub fn walk_enum<V: Visitor + ?Sized>(v: &mut V, n: &EnumNd) {
+    for (var, e) in n.items.iter() {
+        v.visit_var(var);
+        if let Some(e) = e {
+            v.visit_expr(e);
+        }
+    }
+}
+
+pub fn walk_typedef<V: Visitor + ?Sized>(v: &mut V, n: &TypedefNd) {
+    if let Some(s) = &n.def {
+        v.visit_struct(s);
+    }
+}
+
+pub fn walk_declare<V: Visitor + ?Sized>(v: &mut V, n: &DeclareNd) {
+    v.visit_var(&n.var);
+    for b in n.bounds.iter().flatten() {
+        v.visit_expr(b);
+    }
+    if let Some(e) = &n.expr {
+        v.visit_expr(e);
+    }
+    for e in n.list.iter() {
+        v.visit_expr(e);
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(v: &mut V, n: &BlockNd) {
+    for it in n.items.iter() {
+        v.visit_item(it);
+    }
+}
+
+pub fn walk_item<V: Visitor + ?Sized>(v: &mut V, n: &ItemNd) {
+    match n {
+        ItemNd::Stmt(n) => v.visit_stmt(n),
+        ItemNd::Block(n) => v.visit_block(n),
+        ItemNd::If(n) => v.visit_if(n),
+        ItemNd::While(n) => v.visit_while(n),
+        ItemNd::Return(n) => v.visit_return(n),
+        ItemNd::Continue(n) => v.visit_continue(n),
+        ItemNd::Break(n) => v.visit_break(n),
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(v: &mut V, n: &StmtNd) {
+    match n {
+        StmtNd::Assign(n) => v.visit_assign(n),
+        StmtNd::Declare(n) => v.visit_declare(n),
+        StmtNd::Expr(n) => v.visit_expr(n),
+        StmtNd::Print(n) => v.visit_var(n),
+        StmtNd::Empty => {}
+    }
+}
+
+pub fn walk_assign<V: Visitor + ?Sized>(v: &mut V, n: &AssignNd) {
+    v.visit_expr(&n.lhs);
+    v.visit_expr(&n.expr);
+}
+
+pub fn walk_if<V: Visitor + ?Sized>(v: &mut V, n: &IfNd) {
+    v.visit_expr(&n.expr);
+    v.visit_item(&n.item);
+    if let Some(els) = &n.els {
+        v.visit_els(els);
+    }
+}
+
+pub fn walk_els<V: Visitor + ?Sized>(v: &mut V, n: &ElsNd) {
+    match n {
+        ElsNd::If(n) => v.visit_if(n),
+        ElsNd::Item(n) => v.visit_item(n),
+    }
+}
+
+pub fn walk_while<V: Visitor + ?Sized>(v: &mut V, n: &WhileNd) {
+    v.visit_expr(&n.expr);
+    v.visit_item(&n.item);
+}
+
+pub fn walk_return<V: Visitor + ?Sized>(v: &mut V, n: &ReturnNd) {
+    if let Some(e) = &n.expr {
+        v.visit_expr(e);
+    }
+}
+
+// members and casts carry no nodes
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, n: &ExprNd) {
+    for it in n.stack.iter() {
+        if let CalcItem::Factor(f) = it {
+            v.visit_factor(f);
+        }
+    }
+}
+
+pub fn walk_factor<V: Visitor + ?Sized>(v: &mut V, n: &FactorNd) {
+    match n {
+        FactorNd::Var(n) => v.visit_var(n),
+        FactorNd::Value(_) | FactorNd::Str(_) => {}
+        FactorNd::Func(n) => v.visit_func_call(n),
+        FactorNd::Indirect(n) => v.visit_indirect_call(n),
+        FactorNd::Sizeof(n) => v.visit_sizeof(n),
+        FactorNd::Va(n) => v.visit_va(n),
+    }
+}
+
+pub fn walk_func_call<V: Visitor + ?Sized>(v: &mut V, n: &FuncCallNd) {
+    v.visit_var(&n.var);
+    for p in n.params.iter() {
+        v.visit_expr(p);
+    }
+}
+
+pub fn walk_indirect_call<V: Visitor + ?Sized>(v: &mut V, n: &IndirectCallNd) {
+    v.visit_expr(&n.callee);
+    for p in n.params.iter() {
+        v.visit_expr(p);
+    }
+}
+
+pub fn walk_sizeof<V: Visitor + ?Sized>(v: &mut V, n: &SizeofNd) {
+    if let SizeofArg::Expr(e) = &n.arg {
+        v.visit_expr(e);
+    }
+}
+
+pub fn walk_va<V: Visitor + ?Sized>(v: &mut V, n: &VaNd) {
+    match n {
+        VaNd::Start(ap, last) => {
+            v.visit_var(ap);
+            v.visit_var(last);
+        }
+        VaNd::Arg(ap, _) | VaNd::End(ap) => v.visit_var(ap),
+    }
+}
+
+// Same walk over a mutable tree, for transforms
+pub trait VisitorMut {
+    fn visit_root_mut(&mut self, n: &mut RootNd) {
+        walk_root_mut(self, n);
+    }
+
+    fn visit_gitem_mut(&mut self, n: &mut GItemNd) {
+        walk_gitem_mut(self, n);
+    }
+
+    fn visit_func_mut(&mut self, n: &mut FuncNd) {
+        walk_func_mut(self, n);
+    }
+
+    fn visit_struct_mut(&mut self, n: &mut StructNd) {
+        walk_struct_mut(self, n);
+    }
+
+    fn visit_enum_mut(&mut self, n: &mut EnumNd) {
+        walk_enum_mut(self, n);
+    }
+
+    fn visit_typedef_mut(&mut self, n: &mut TypedefNd) {
+        walk_typedef_mut(self, n);
+    }
+
+    fn visit_declare_mut(&mut self, n: &mut DeclareNd) {
+        walk_declare_mut(self, n);
+    }
+
+    fn visit_block_mut(&mut self, n: &mut BlockNd) {
+        walk_block_mut(self, n);
+    }
+
+    fn visit_item_mut(&mut self, n: &mut ItemNd) {
+        walk_item_mut(self, n);
+    }
+
+    fn visit_stmt_mut(&mut self, n: &mut StmtNd) {
+        walk_stmt_mut(self, n);
+    }
+
+    fn visit_assign_mut(&mut self, n: &mut AssignNd) {
+        walk_assign_mut(self, n);
+    }
+
+    fn visit_if_mut(&mut self, n: &mut IfNd) {
+        walk_if_mut(self, n);
+    }
+
+    fn visit_els_mut(&mut self, n: &mut ElsNd) {
+        walk_els_mut(self, n);
+    }
+
+    fn visit_while_mut(&mut self, n: &mut WhileNd) {
+        walk_while_mut(self, n);
+    }
+
+    fn visit_return_mut(&mut self, n: &mut ReturnNd) {
+        walk_return_mut(self, n);
+    }
+
+    fn visit_break_mut(&mut self, _n: &mut BreakNd) {}
+
+    fn visit_continue_mut(&mut self, _n: &mut ContinueNd) {}
+
+    fn visit_expr_mut(&mut self, n: &mut ExprNd) {
+        walk_expr_mut(self, n);
+    }
+
+    fn visit_factor_mut(&mut self, n: &mut FactorNd) {
+        walk_factor_mut(self, n);
+    }
+
+    fn visit_var_mut(&mut self, _n: &mut VarNd) {}
+
+    fn visit_func_call_mut(&mut self, n: &mut FuncCallNd) {
+        walk_func_call_mut(self, n);
+    }
+
+    fn visit_indirect_call_mut(&mut self, n: &mut IndirectCallNd) {
+        walk_indirect_call_mut(self, n);
+    }
+
+    fn visit_sizeof_mut(&mut self, n: &mut SizeofNd) {
+        walk_sizeof_mut(self, n);
+    }
+
+    fn visit_va_mut(&mut self, n: &mut VaNd) {
+        walk_va_mut(self, n);
+    }
+}
+
+pub fn walk_root_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut RootNd) {
+    for it in n.items.iter_mut() {
+        v.visit_gitem_mut(it);
+    }
+}
+
+pub fn walk_gitem_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut GItemNd) {
+    match n {
+        GItemNd::Declare(n) => v.visit_declare_mut(n),
+        GItemNd::Func(n) => v.visit_func_mut(n),
+        GItemNd::Struct(n) => v.visit_struct_mut(n),
+        GItemNd::Enum(n) => v.visit_enum_mut(n),
+        GItemNd::Typedef(n) => v.visit_typedef_mut(n),
+    }
+}
+
+pub fn walk_func_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut FuncNd) {
+    v.visit_var_mut(&mut n.var);
+    for (_, p) in n.params.iter_mut() {
+        if let Some(p) = p {
+            v.visit_var_mut(p);
+        }
+    }
+    if let Some(b) = &mut n.block {
+        v.visit_block_mut(b);
+    }
+}
+
+pub fn walk_struct_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut StructNd) {
+    for f in n.fields.iter_mut() {
+        v.visit_declare_mut(f);
+    }
+}
+
+pub fn walk_enum_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut EnumNd) {
+    for (var, e) in n.items.iter_mut() {
+        v.visit_var_mut(var);
+        if let Some(e) = e {
+            v.visit_expr_mut(e);
+        }
+    }
+}
+
+pub fn walk_typedef_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut TypedefNd) {
+    if let Some(s) = &mut n.def {
+        v.visit_struct_mut(s);
+    }
+}
+
+pub fn walk_declare_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut DeclareNd) {
+    v.visit_var_mut(&mut n.var);
+    for b in n.bounds.iter_mut().flatten() {
+        v.visit_expr_mut(b);
+    }
+    if let Some(e) = &mut n.expr {
+        v.visit_expr_mut(e);
+    }
+    for e in n.list.iter_mut() {
+        v.visit_expr_mut(e);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut BlockNd) {
+    for it in n.items.iter_mut() {
+        v.visit_item_mut(it);
+    }
+}
+
+pub fn walk_item_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut ItemNd) {
+    match n {
+        ItemNd::Stmt(n) => v.visit_stmt_mut(n),
+        ItemNd::Block(n) => v.visit_block_mut(n),
+        ItemNd::If(n) => v.visit_if_mut(n),
+        ItemNd::While(n) => v.visit_while_mut(n),
+        ItemNd::Return(n) => v.visit_return_mut(n),
+        ItemNd::Continue(n) => v.visit_continue_mut(n),
+        ItemNd::Break(n) => v.visit_break_mut(n),
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut StmtNd) {
+    match n {
+        StmtNd::Assign(n) => v.visit_assign_mut(n),
+        StmtNd::Declare(n) => v.visit_declare_mut(n),
+        StmtNd::Expr(n) => v.visit_expr_mut(n),
+        StmtNd::Print(n) => v.visit_var_mut(n),
+        StmtNd::Empty => {}
+    }
+}
+
+pub fn walk_assign_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut AssignNd) {
+    v.visit_expr_mut(&mut n.lhs);
+    v.visit_expr_mut(&mut n.expr);
+}
+
+pub fn walk_if_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut IfNd) {
+    v.visit_expr_mut(&mut n.expr);
+    v.visit_item_mut(&mut n.item);
+    if let Some(els) = &mut n.els {
+        v.visit_els_mut(els);
+    }
+}
+
+pub fn walk_els_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut ElsNd) {
+    match n {
+        ElsNd::If(n) => v.visit_if_mut(n),
+        ElsNd::Item(n) => v.visit_item_mut(n),
+    }
+}
+
+pub fn walk_while_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut WhileNd) {
+    v.visit_expr_mut(&mut n.expr);
+    v.visit_item_mut(&mut n.item);
+}
+
+pub fn walk_return_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut ReturnNd) {
+    if let Some(e) = &mut n.expr {
+        v.visit_expr_mut(e);
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut ExprNd) {
+    for it in n.stack.iter_mut() {
+        if let CalcItem::Factor(f) = it {
+            v.visit_factor_mut(f);
+        }
+    }
+}
+
+pub fn walk_factor_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut FactorNd) {
+    match n {
+        FactorNd::Var(n) => v.visit_var_mut(n),
+        FactorNd::Value(_) | FactorNd::Str(_) => {}
+        FactorNd::Func(n) => v.visit_func_call_mut(n),
+        FactorNd::Indirect(n) => v.visit_indirect_call_mut(n),
+        FactorNd::Sizeof(n) => v.visit_sizeof_mut(n),
+        FactorNd::Va(n) => v.visit_va_mut(n),
+    }
+}
+
+pub fn walk_func_call_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut FuncCallNd) {
+    v.visit_var_mut(&mut n.var);
+    for p in n.params.iter_mut() {
+        v.visit_expr_mut(p);
+    }
+}
+
+pub fn walk_indirect_call_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut IndirectCallNd) {
+    v.visit_expr_mut(&mut n.callee);
+    for p in n.params.iter_mut() {
+        v.visit_expr_mut(p);
+    }
+}
+
+pub fn walk_sizeof_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut SizeofNd) {
+    if let SizeofArg::Expr(e) = &mut n.arg {
+        v.visit_expr_mut(e);
+    }
+}
+
+pub fn walk_va_mut<V: VisitorMut + ?Sized>(v: &mut V, n: &mut VaNd) {
+    match n {
+        VaNd::Start(ap, last) => {
+            v.visit_var_mut(ap);
+            v.visit_var_mut(last);
+        }
+        VaNd::Arg(ap, _) | VaNd::End(ap) => v.visit_var_mut(ap),
+    }
+}
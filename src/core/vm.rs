@@ -1,32 +1,101 @@
-use super::compiler::Program;
-use super::types::{calc_op_1, calc_op_2, get_op_param_num, Code, CodeAddr, ErrKind, MemAddr};
+mod builder;
+mod heap;
+mod input;
+mod native;
+mod output;
+
+use super::compiler::{compile_with_natives, FuncSymbol, Program};
+use super::types::{
+    calc_op_1, calc_op_2, calc_trunc, get_op_param_num, Builtin, Code, CodeAddr, ErrKind, Error,
+    MemAddr, RuntimeError, SemanticError, Type, Value,
+};
+pub use builder::VmBuilder;
+use heap::Heap;
+pub use heap::HeapStats;
+use input::Reader;
+pub use input::{FnInput, Input, StdInput, StrInput};
+use native::Native;
+pub use output::{BufferOutput, FnOutput, Output, StdOutput, Stream};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_HEAP_SIZE: usize = 4096;
+const DEFAULT_MAX_CALL_DEPTH: usize = 10000;
+// instructions between two looks at the clock
+const CLOCK_INTERVAL: u64 = 1024;
 
 pub struct VM {
+    mem_len: usize,
+    rodata: Vec<(usize, usize)>, // read-only ranges of memory
     pc: usize,
     ps: usize,
     pd: usize,
     codes: Vec<Code>,
+    funcs: Vec<FuncSymbol>,
     datas: Vec<i32>,
-    control_stack: Vec<usize>,
+    control_stack: Vec<usize>, // pc and pd of each caller
+    max_call_depth: usize,
+    heap: Heap, // above the data stack
+    output: Box<dyn Output>,
+    input: Reader,
+    print_prefix: bool, // `Print: ` before the values of print statements
     stop: bool,
+    exit_code: Option<i32>,
+    instructions: u64, // executed by this run
+    max_instructions: Option<u64>,
+    timeout: Option<Duration>, // of each execute
+    deadline: Option<Instant>,
+    interrupt: Option<Arc<AtomicBool>>,
+    natives: Vec<Native>, // CallNative indexes these
 }
 
 #[allow(dead_code)]
 impl VM {
     pub fn new(data_stack_size: usize, prog: Program) -> Self {
+        VM::with_heap(data_stack_size, DEFAULT_HEAP_SIZE, prog)
+    }
+
+    pub fn builder() -> VmBuilder {
+        VmBuilder::new()
+    }
+
+    // default sizes, see VmBuilder for others
+    pub fn from_source(code: &str) -> Result<Self, Error> {
+        VmBuilder::new().compile(code)
+    }
+
+    // panics when memory doesn't fit, VmBuilder::build returns an error
+    pub fn with_heap(data_stack_size: usize, heap_size: usize, prog: Program) -> Self {
         let mem_len = prog.memory.len();
         if data_stack_size < mem_len + 100 {
             panic!("Too low vm size");
         }
         // println!("memory {:?}", prog.memory);
         let mut vm = VM {
+            mem_len,
+            rodata: vec![(prog.rodata, mem_len)],
             pc: prog.start_pc,
             pd: mem_len,
             ps: mem_len,
-            datas: vec![0; data_stack_size],
+            datas: vec![0; data_stack_size + heap_size],
             codes: prog.codes,
+            funcs: prog.funcs,
             control_stack: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            heap: Heap::new(data_stack_size, heap_size),
+            output: Box::new(StdOutput),
+            input: Reader::new(Box::new(StdInput)),
+            print_prefix: true,
             stop: false,
+            exit_code: None,
+            instructions: 0,
+            max_instructions: None,
+            timeout: None,
+            deadline: None,
+            interrupt: None,
+            natives: Vec::new(),
         };
         for (i, x) in prog.memory.iter().enumerate() {
             vm.datas[i] = *x;
@@ -34,23 +103,167 @@ impl VM {
         vm
     }
 
-    fn get_code_addr(&self, addr: CodeAddr) -> usize {
-        match addr {
-            CodeAddr::Direct(a) => a,
-            _ => panic!("name addr in vm!"),
+    // Next program of a repl session, compiled on top of the last one.
+    // Memory up to `mem_len` keeps its values, the rest is new
+    pub(crate) fn reload(&mut self, prog: Program) -> Result<(), ErrKind> {
+        let mem_len = prog.memory.len();
+        if mem_len + 100 > self.heap.start() {
+            return Err(ErrKind::StackOverFlow(0, entry_name(&prog)));
         }
+        self.datas[self.mem_len..mem_len].copy_from_slice(&prog.memory[self.mem_len..]);
+        if prog.rodata < mem_len {
+            self.rodata.push((prog.rodata, mem_len));
+        }
+        self.mem_len = mem_len;
+        self.codes = prog.codes;
+        self.funcs = prog.funcs;
+        self.pc = prog.start_pc;
+        self.ps = mem_len;
+        self.pd = mem_len;
+        self.control_stack.clear();
+        self.stop = false;
+        self.exit_code = None;
+        Ok(())
+    }
+
+    // Makes `name` callable from programs loaded later, as if it was
+    // implemented in C with these param and result types. Params and the
+    // result are single cells, chars and pointers as ints. An Err aborts
+    // the run with NativeErr. Registering a name again replaces the function
+    pub fn register_native<F>(&mut self, name: &str, params: &[Type], ret: Type, f: F)
+    where
+        F: FnMut(&[i32]) -> Result<i32, String> + Send + 'static,
+    {
+        let native = Native::new(name, params, ret, Box::new(f));
+        match self.natives.iter_mut().find(|n| n.name == name) {
+            Some(n) => *n = native,
+            None => self.natives.push(native),
+        }
+    }
+
+    // Compiles `code` against the registered natives and makes it the
+    // program to execute. Memory and the heap start over
+    pub fn load(&mut self, code: &str) -> Result<(), Error> {
+        let sigs: Vec<(String, Type)> = self
+            .natives
+            .iter()
+            .map(|n| (n.name.clone(), n.ty.clone()))
+            .collect();
+        let prog = compile_with_natives(code, &sigs)?;
+        let stack_size = self.heap.start();
+        check_fits(stack_size, &prog)?;
+        let mut vm = VM::with_heap(stack_size, self.datas.len() - stack_size, prog);
+        std::mem::swap(&mut vm.output, &mut self.output);
+        std::mem::swap(&mut vm.input, &mut self.input);
+        std::mem::swap(&mut vm.natives, &mut self.natives);
+        vm.print_prefix = self.print_prefix;
+        vm.max_instructions = self.max_instructions;
+        vm.max_call_depth = self.max_call_depth;
+        vm.timeout = self.timeout;
+        vm.interrupt = self.interrupt.take();
+        *self = vm;
+        Ok(())
     }
 
-    fn geta(&self, addr: MemAddr) -> usize {
+    // Runs the implemented function `name` to its return on an empty stack.
+    // Globals and the heap are as earlier runs left them. Args are checked
+    // against the params, structs and variadic functions can't be called
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, Error> {
+        let type_err = || {
+            Error::from(SemanticError {
+                kind: ErrKind::TypeErr,
+            })
+        };
+        let f = match self.funcs.iter().find(|f| f.name == name) {
+            Some(f) => f,
+            None => {
+                return Err(SemanticError {
+                    kind: ErrKind::NoDeclare,
+                }
+                .into())
+            }
+        };
+        let (ret, params) = match &f.ty {
+            Type::Func(v, false) => v.split_last().unwrap(),
+            _ => return Err(type_err()),
+        };
+        let ret = ret.unqualified().clone();
+        if params.len() != args.len() || !(ret.is_scalar() || ret == Type::Void) {
+            return Err(type_err());
+        }
+        let mut cells = Vec::new();
+        for (t, v) in params.iter().zip(args.iter()) {
+            match (t.unqualified(), v) {
+                (Type::Char, Value::Int(n)) => cells.push(calc_trunc(*n, 8)),
+                (t, Value::Int(n)) if t.is_scalar() => cells.push(*n),
+                _ => return Err(type_err()),
+            }
+        }
+        let pc = f.pc;
+        self.control_stack.clear();
+        self.ps = self.mem_len;
+        self.pd = self.mem_len;
+        for c in cells {
+            self.pushv(c).map_err(|kind| RuntimeError { kind, pc })?;
+        }
+        self.pc = pc;
+        self.stop = false;
+        self.exit_code = None;
+        self.execute()?;
+        Ok(match ret {
+            Type::Void => Value::Void,
+            _ => Value::Int(self.exit_code.unwrap_or(0)),
+        })
+    }
+
+    fn call_native(&mut self, idx: usize, argc: usize) -> Result<(), ErrKind> {
+        let base = self.drop_cells(argc)?;
+        let args = self.datas[base..base + argc].to_vec();
+        let native = self
+            .natives
+            .get_mut(idx)
+            .ok_or_else(|| ErrKind::NativeErr(format!("no native function {}", idx)))?;
+        match native.call(&args)? {
+            Some(v) => self.pushv(v),
+            None => Ok(()),
+        }
+    }
+
+    // named addresses are left by a program that was never linked
+    fn get_code_addr(&self, addr: CodeAddr) -> Result<usize, ErrKind> {
         match addr {
-            MemAddr::Direct(a) => a,
-            MemAddr::Indirect(a) => self.pd + a,
+            CodeAddr::Direct(a) => Ok(a),
+            _ => Err(ErrKind::InvalidAddress),
         }
     }
 
+    fn geta(&self, addr: MemAddr) -> Result<usize, ErrKind> {
+        let a = match addr {
+            MemAddr::Direct(a) => Some(a),
+            MemAddr::Indirect(a) => self.pd.checked_add(a),
+        };
+        a.filter(|a| *a < self.datas.len())
+            .ok_or(ErrKind::InvalidAddress)
+    }
+
+    // name of the function the code at `pc` belongs to
+    fn func_at(&self, pc: usize) -> String {
+        self.symbol_at(pc)
+            .map_or_else(String::new, |f| f.name.clone())
+    }
+
+    fn symbol_at(&self, pc: usize) -> Option<&FuncSymbol> {
+        self.funcs.iter().rev().find(|f| f.pc <= pc)
+    }
+
+    fn call_depth(&self) -> usize {
+        self.control_stack.len() / 2
+    }
+
     fn pushv(&mut self, x: i32) -> Result<(), ErrKind> {
-        if self.ps >= self.datas.len() {
-            Err(ErrKind::StackOverFlow)
+        if self.ps >= self.heap.start() {
+            let func = self.func_at(self.pc.saturating_sub(1));
+            Err(ErrKind::StackOverFlow(self.call_depth(), func))
         } else {
             self.datas[self.ps] = x;
             self.ps += 1;
@@ -58,97 +271,590 @@ impl VM {
         }
     }
 
-    fn popv(&mut self) -> i32 {
-        self.ps -= 1;
-        self.datas[self.ps]
+    // address must be inside memory (except null), the live stack under `top`
+    // or a live heap block
+    fn check_addr(&self, addr: i32, sz: usize, top: usize) -> Result<usize, ErrKind> {
+        if addr <= 0 {
+            return Err(ErrKind::InvalidAddress);
+        }
+        let a = addr as usize;
+        let end = a.checked_add(sz).ok_or(ErrKind::InvalidAddress)?;
+        if self.heap.contains(a) {
+            self.heap.check(a, sz)?;
+            Ok(a)
+        } else if end <= self.mem_len || (a >= self.mem_len && end <= top) {
+            Ok(a)
+        } else {
+            Err(ErrKind::InvalidAddress)
+        }
+    }
+
+    fn check_write(&self, addr: usize, sz: usize) -> Result<(), ErrKind> {
+        if self.rodata.iter().any(|(s, e)| addr < *e && addr + sz > *s) {
+            Err(ErrKind::WriteReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    // bytes up to the terminating zero
+    fn read_str(&self, addr: i32) -> Result<Vec<u8>, ErrKind> {
+        let mut s = Vec::new();
+        let mut a = addr;
+        loop {
+            let c = self.datas[self.check_addr(a, 1, self.ps)?];
+            if c == 0 {
+                return Ok(s);
+            }
+            s.push(c as u8);
+            a += 1;
+        }
+    }
+
+    fn output(&mut self, s: &[u8]) {
+        self.output.write(Stream::Out, s);
+    }
+
+    pub fn set_output<O: Output + 'static>(&mut self, output: O) {
+        self.output = Box::new(output);
+    }
+
+    // text not read yet by the old input is dropped
+    pub fn set_input<I: Input + 'static>(&mut self, input: I) {
+        self.input = Reader::new(Box::new(input));
+    }
+
+    // to the err stream of the output
+    pub fn warn(&mut self, msg: &str) {
+        self.output
+            .write(Stream::Err, format!("Warning: {}\n", msg).as_bytes());
+    }
+
+    fn call_builtin(&mut self, b: Builtin) -> Result<(), ErrKind> {
+        match b {
+            Builtin::Puts => {
+                let addr = self.popv()?;
+                let s = self.read_str(addr)?;
+                self.output(&s);
+                self.output(b"\n");
+                self.pushv(0)
+            }
+            Builtin::Putchar => {
+                let c = self.popv()?;
+                self.output(&[c as u8]);
+                self.pushv(c)
+            }
+            Builtin::Printf => {
+                let addr = self.popv()?;
+                let cells = self.popv()? as usize;
+                let base = self.drop_cells(cells)?;
+                let args = self.datas[base..base + cells].to_vec();
+                let fmt = self.read_str(addr)?;
+                let s = self.format(&fmt, &args)?;
+                self.output(&s);
+                self.pushv(s.len() as i32)
+            }
+            Builtin::Malloc => {
+                let sz = self.popv()?;
+                let a = self.malloc(sz, false);
+                self.pushv(a)
+            }
+            Builtin::Calloc => {
+                let sz = self.popv()?;
+                let n = self.popv()?;
+                let a = self.malloc(n.saturating_mul(sz), true);
+                self.pushv(a)
+            }
+            Builtin::Realloc => {
+                let sz = self.popv()?;
+                let addr = self.popv()?;
+                let a = self.realloc(addr, sz)?;
+                self.pushv(a)
+            }
+            Builtin::Free => {
+                let addr = self.popv()?;
+                self.free(addr)
+            }
+            Builtin::Getchar => {
+                let c = self.input.next().map_or(-1, |c| c as i32);
+                self.pushv(c)
+            }
+            Builtin::ReadInt => {
+                self.input.skip_space();
+                let v = self.input.int().unwrap_or(-1);
+                self.pushv(v)
+            }
+            Builtin::Scanf => {
+                let addr = self.popv()?;
+                let cells = self.popv()? as usize;
+                let base = self.drop_cells(cells)?;
+                let args = self.datas[base..base + cells].to_vec();
+                let fmt = self.read_str(addr)?;
+                let n = self.scan(&fmt, &args)?;
+                self.pushv(n)
+            }
+        }
+    }
+
+    fn store(&mut self, addr: i32, v: i32) -> Result<(), ErrKind> {
+        let a = self.check_addr(addr, 1, self.ps)?;
+        self.check_write(a, 1)?;
+        self.datas[a] = v;
+        Ok(())
+    }
+
+    // %d %c %s, or %%; white space skips any, other chars must match.
+    // Values assigned, -1 if the input ended before the first
+    fn scan(&mut self, fmt: &[u8], args: &[i32]) -> Result<i32, ErrKind> {
+        let mut n = 0;
+        let ended = |n| if n == 0 { -1 } else { n };
+        let mut args = args.iter();
+        let mut it = fmt.iter().copied();
+        while let Some(c) = it.next() {
+            if c.is_ascii_whitespace() {
+                self.input.skip_space();
+                continue;
+            }
+            let conv = match c {
+                b'%' => it.next().ok_or(ErrKind::BadFormat)?,
+                _ => 0, // a plain char
+            };
+            if conv == 0 || conv == b'%' {
+                match self.input.peek() {
+                    None => return Ok(ended(n)),
+                    Some(x) if x == c => self.input.next(),
+                    Some(_) => break,
+                };
+                continue;
+            }
+            let addr = *args.next().ok_or(ErrKind::BadFormat)?;
+            let more = match conv {
+                b'd' | b's' => self.input.skip_space(),
+                _ => self.input.peek().is_some(),
+            };
+            if !more {
+                return Ok(ended(n));
+            }
+            match conv {
+                b'd' => match self.input.int() {
+                    Some(v) => self.store(addr, v)?,
+                    None => break,
+                },
+                b'c' => {
+                    let c = self.input.next().unwrap();
+                    self.store(addr, c as i8 as i32)?;
+                }
+                b's' => {
+                    let w = self.input.word();
+                    for (i, c) in w.iter().chain([0].iter()).enumerate() {
+                        self.store(addr + i as i32, *c as i8 as i32)?;
+                    }
+                }
+                _ => return Err(ErrKind::BadFormat),
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    // null when out of memory
+    fn malloc(&mut self, sz: i32, zero: bool) -> i32 {
+        if sz <= 0 {
+            return 0;
+        }
+        match self.heap.alloc(sz as usize) {
+            Some(a) => {
+                if zero {
+                    for i in 0..sz as usize {
+                        self.datas[a + i] = 0;
+                    }
+                }
+                a as i32
+            }
+            None => 0,
+        }
+    }
+
+    fn free(&mut self, addr: i32) -> Result<(), ErrKind> {
+        match addr {
+            0 => Ok(()),
+            a if a < 0 || !self.heap.contains(a as usize) => Err(ErrKind::InvalidFree),
+            a => self.heap.free(a as usize).map(|_| ()),
+        }
+    }
+
+    // moves the block, a failed realloc keeps the old one
+    fn realloc(&mut self, addr: i32, sz: i32) -> Result<i32, ErrKind> {
+        if addr == 0 {
+            return Ok(self.malloc(sz, false));
+        }
+        let old = match self.heap.block_size(addr as usize) {
+            Some(n) => n,
+            // not a live block, fails as a free of it
+            None => return self.free(addr).map(|_| 0),
+        };
+        if sz <= 0 {
+            self.free(addr)?;
+            return Ok(0);
+        }
+        let a = self.malloc(sz, false);
+        if a != 0 {
+            let n = old.min(sz as usize);
+            for i in 0..n {
+                self.datas[a as usize + i] = self.datas[addr as usize + i];
+            }
+            self.free(addr)?;
+        }
+        Ok(a)
+    }
+
+    // what main returned, None until it did or for void main
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    // of the last execute or call
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    // blocks still allocated, (addr, size), leaks once the program stopped
+    pub fn heap_leaks(&self) -> Vec<(usize, usize)> {
+        self.heap.leaks()
+    }
+
+    // %[-][0][width] then one of d i u x c s, or %%; bytes as printed
+    fn format(&self, fmt: &[u8], args: &[i32]) -> Result<Vec<u8>, ErrKind> {
+        let mut s = Vec::new();
+        let mut args = args.iter();
+        let mut it = fmt.iter().copied().peekable();
+        while let Some(c) = it.next() {
+            if c != b'%' {
+                s.push(c);
+                continue;
+            }
+            let left = it.next_if_eq(&b'-').is_some();
+            let zero = it.next_if_eq(&b'0').is_some();
+            let mut width = 0;
+            while let Some(d) = it.next_if(u8::is_ascii_digit) {
+                width = width * 10 + (d - b'0') as usize;
+            }
+            let conv = it.next().ok_or(ErrKind::BadFormat)?;
+            if conv == b'%' {
+                s.push(b'%');
+                continue;
+            }
+            let v = *args.next().ok_or(ErrKind::BadFormat)?;
+            let (mut text, numeric) = match conv {
+                b'd' | b'i' => (v.to_string().into_bytes(), true),
+                b'u' => ((v as u32).to_string().into_bytes(), true),
+                b'x' => (format!("{:x}", v as u32).into_bytes(), true),
+                b'c' => (vec![v as u8], false),
+                b's' => (self.read_str(v)?, false),
+                _ => return Err(ErrKind::BadFormat),
+            };
+            let pad = width.saturating_sub(text.len());
+            if left {
+                text.resize(text.len() + pad, b' ');
+            } else if zero && numeric {
+                // zeros go after the sign
+                let at = usize::from(text.first() == Some(&b'-'));
+                text.splice(at..at, std::iter::repeat(b'0').take(pad));
+            } else {
+                text.splice(0..0, std::iter::repeat(b' ').take(pad));
+            }
+            s.extend(text);
+        }
+        Ok(s)
+    }
+
+    fn enter_call(&mut self, target: usize, num_params: usize) -> Result<(), ErrKind> {
+        let depth = self.call_depth() + 1;
+        if depth > self.max_call_depth {
+            return Err(ErrKind::StackOverFlow(depth, self.func_at(target)));
+        }
+        let pd = self.frame_floor(self.ps.checked_sub(num_params))?;
+        self.control_stack.push(self.pc);
+        self.control_stack.push(self.pd);
+        self.pd = pd;
+        self.pc = target;
+        Ok(())
+    }
+
+    // result moves down to `base`
+    fn ret(&mut self, sz: usize, base: usize) -> Result<(), ErrKind> {
+        // ret main
+        if self.control_stack.is_empty() {
+            self.stop = true;
+            if sz > 0 {
+                self.exit_code = Some(self.datas[self.top()?]);
+            }
+            return Ok(());
+        }
+        let from = self.drop_cells(sz)?;
+        let base = self.frame_floor(Some(base).filter(|b| *b <= from))?;
+        self.pd = self.control_stack.pop().ok_or(ErrKind::StackUnderflow)?;
+        self.pc = self.control_stack.pop().ok_or(ErrKind::StackUnderflow)?;
+        for i in 0..sz {
+            self.datas[base + i] = self.datas[from + i];
+        }
+        self.ps = base + sz;
+        Ok(())
+    }
+
+    // the stack starts after memory, nothing goes under it
+    fn frame_floor(&self, a: Option<usize>) -> Result<usize, ErrKind> {
+        a.filter(|a| *a >= self.mem_len)
+            .ok_or(ErrKind::StackUnderflow)
+    }
+
+    // n cells off the stack, the new top is returned
+    fn drop_cells(&mut self, n: usize) -> Result<usize, ErrKind> {
+        self.ps = self.frame_floor(self.ps.checked_sub(n))?;
+        Ok(self.ps)
+    }
+
+    fn popv(&mut self) -> Result<i32, ErrKind> {
+        let a = self.drop_cells(1)?;
+        Ok(self.datas[a])
+    }
+
+    // address of the top cell
+    fn top(&self) -> Result<usize, ErrKind> {
+        self.frame_floor(self.ps.checked_sub(1))
+    }
+
+    // under a variadic frame are its args, then their count
+    fn variadic_base(&self) -> Result<usize, ErrKind> {
+        self.variadic_args(self.pd).map(|(base, _)| base)
+    }
+
+    // [base, end) of the args under the variadic frame at pd
+    fn variadic_args(&self, pd: usize) -> Result<(usize, usize), ErrKind> {
+        let count = self.frame_floor(pd.checked_sub(1))?;
+        let cells = usize::try_from(self.datas[count]).ok();
+        let base = self.frame_floor(cells.and_then(|n| count.checked_sub(n)))?;
+        Ok((base, count))
+    }
+
+    // a va_list may be handed down, so any live variadic frame may own it
+    fn check_va(&self, ap: i32, sz: usize) -> Result<(), ErrKind> {
+        let callers = self.control_stack.chunks(2).rev();
+        let frames = callers.map(|c| (c[0].saturating_sub(1), c[1]));
+        for (pc, pd) in std::iter::once((self.pc.saturating_sub(1), self.pd)).chain(frames) {
+            let variadic = self
+                .symbol_at(pc)
+                .map_or(false, |f| matches!(f.ty, Type::Func(_, true)));
+            if !variadic {
+                continue;
+            }
+            let (base, end) = self.variadic_args(pd)?;
+            let a = usize::try_from(ap).unwrap_or(0);
+            if base <= a && a + sz <= end {
+                return Ok(());
+            }
+        }
+        Err(ErrKind::InvalidAddress)
+    }
+
+    // The run stops once the flag is set, it is left set
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    fn check_limits(&self) -> Result<(), ErrKind> {
+        let n = self.instructions;
+        if self.max_instructions.map_or(false, |max| n >= max) {
+            return Err(ErrKind::InstructionLimit(n));
+        }
+        if let Some(flag) = &self.interrupt {
+            if flag.load(Ordering::Relaxed) {
+                return Err(ErrKind::Interrupted(n));
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if n % CLOCK_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err(ErrKind::Timeout(n));
+            }
+        }
+        Ok(())
     }
 
     // ret output
     pub fn execute_once(&mut self) -> Result<Option<i32>, ErrKind> {
-        let code = self.codes[self.pc].clone();
+        self.check_limits()?;
+        self.instructions += 1;
+        let code = *self.codes.get(self.pc).ok_or(ErrKind::InvalidAddress)?;
         self.pc += 1;
         match code {
             Code::PushValue(x) => {
                 self.pushv(x)?;
             }
             Code::Push(addr) => {
-                let a = self.geta(addr);
+                let a = self.geta(addr)?;
                 self.pushv(self.datas[a])?;
             }
+            Code::PushAddr(addr) => {
+                let a = self.geta(addr)?;
+                self.pushv(a as i32)?;
+            }
+            Code::Reserve(sz) => {
+                for _ in 0..sz {
+                    self.pushv(0)?;
+                }
+            }
+            Code::Load(sz) => {
+                let addr = self.popv()?;
+                let a = self.check_addr(addr, sz, self.ps)?;
+                for i in 0..sz {
+                    self.pushv(self.datas[a + i])?;
+                }
+            }
+            Code::Store(sz) => {
+                let base = self.drop_cells(sz)?;
+                let addr = self.popv()?;
+                let a = self.check_addr(addr, sz, self.ps)?;
+                self.check_write(a, sz)?;
+                for i in 0..sz {
+                    self.datas[a + i] = self.datas[base + i];
+                }
+            }
             Code::Pop(sz) => {
-                self.ps -= sz;
+                self.drop_cells(sz)?;
             }
             Code::PopMov(addr) => {
-                let a = self.geta(addr);
-                self.datas[a] = self.popv();
+                let a = self.geta(addr)?;
+                self.datas[a] = self.popv()?;
+            }
+            Code::Trunc(bits) => {
+                if bits == 0 || bits > 32 {
+                    return Err(ErrKind::InvalidCode);
+                }
+                let t = self.top()?;
+                self.datas[t] = calc_trunc(self.datas[t], bits);
             }
             Code::Op(op) => match get_op_param_num(op) {
                 1 => {
-                    self.datas[self.ps - 1] = calc_op_1(op, self.datas[self.ps - 1])?;
+                    let t = self.top()?;
+                    self.datas[t] = calc_op_1(op, self.datas[t])?;
                 }
                 2 => {
-                    self.datas[self.ps - 2] =
-                        calc_op_2(op, self.datas[self.ps - 2], self.datas[self.ps - 1])?;
-                    self.ps -= 1;
-                }
-                _ => {
-                    panic!("No {} param num", get_op_param_num(op));
+                    let b = self.popv()?;
+                    let t = self.top()?;
+                    self.datas[t] = calc_op_2(op, self.datas[t], b)?;
                 }
+                _ => return Err(ErrKind::InvalidCode),
             },
+            Code::PushFunc(code_addr) => {
+                let a = self.get_code_addr(code_addr)?;
+                self.pushv(a as i32)?;
+            }
             Code::Call(code_addr, num_params) => {
-                self.control_stack.push(self.pc);
-                self.control_stack.push(self.pd);
-                self.pd = self.ps - num_params;
-                self.pc = self.get_code_addr(code_addr);
+                let target = self.get_code_addr(code_addr)?;
+                self.enter_call(target, num_params)?;
+            }
+            Code::CallIndirect(num_params) => {
+                let target = self.popv()?;
+                // 0 is null, no function starts there
+                if target <= 0 || target as usize >= self.codes.len() {
+                    return Err(ErrKind::InvalidAddress);
+                }
+                self.enter_call(target as usize, num_params)?;
+            }
+            Code::Builtin(b) => {
+                self.call_builtin(b)?;
+            }
+            Code::CallNative(idx, argc) => {
+                self.call_native(idx, argc)?;
             }
             Code::Jump(code_addr) => {
-                self.pc = self.get_code_addr(code_addr);
+                self.pc = self.get_code_addr(code_addr)?;
             }
             Code::CondJump(code_addr) => {
-                if self.popv() == 0 {
-                    self.pc = self.get_code_addr(code_addr);
+                if self.popv()? == 0 {
+                    self.pc = self.get_code_addr(code_addr)?;
                 }
             }
             Code::Print => {
-                let v = self.datas[self.ps - 1];
-                println!("Print: {}", v);
+                let v = self.datas[self.top()?];
+                let line = if self.print_prefix {
+                    format!("Print: {}\n", v)
+                } else {
+                    format!("{}\n", v)
+                };
+                self.output.write(Stream::Out, line.as_bytes());
                 return Ok(Some(v));
             }
             Code::Ret(sz) => {
-                // ret main
-                if self.control_stack.len() == 0 {
-                    self.stop = true;
-                } else {
-                    let nps = self.pd;
-                    self.pd = self.control_stack.pop().unwrap();
-                    self.pc = self.control_stack.pop().unwrap();
-                    self.ps -= sz;
-                    for i in 0..sz {
-                        self.datas[nps + i] = self.datas[self.ps + i];
-                    }
-                    self.ps = nps + sz;
-                }
+                self.ret(sz, self.pd)?;
+            }
+            Code::RetVariadic(sz) => {
+                let base = self.variadic_base()?;
+                self.ret(sz, base)?;
+            }
+            Code::VaStart => {
+                let base = self.variadic_base()?;
+                self.pushv(base as i32)?;
+            }
+            Code::VaArg(sz) => {
+                let ap = self.datas[self.top()?];
+                self.check_va(ap, sz)?;
             }
         }
         Ok(None)
     }
 
-    pub fn execute(&mut self) -> Result<Vec<i32>, ErrKind> {
+    // the budget and the timeout count from here
+    pub fn execute(&mut self) -> Result<Vec<i32>, RuntimeError> {
+        self.instructions = 0;
+        self.deadline = self.timeout.map(|t| Instant::now() + t);
         let mut outs = Vec::new();
         while !self.stop {
-            if let Some(x) = self.execute_once()? {
-                outs.push(x);
+            let pc = self.pc;
+            match self.execute_once() {
+                Ok(Some(x)) => outs.push(x),
+                Ok(None) => {}
+                Err(kind) => return Err(RuntimeError { kind, pc }),
             }
         }
         Ok(outs)
     }
 }
 
+// memory is at the bottom of the stack, with some room left above it
+pub(crate) fn check_fits(stack_size: usize, prog: &Program) -> Result<(), RuntimeError> {
+    if prog.memory.len() + 100 > stack_size {
+        Err(RuntimeError {
+            kind: ErrKind::StackOverFlow(0, entry_name(prog)),
+            pc: prog.start_pc,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+// the function a program starts in
+fn entry_name(prog: &Program) -> String {
+    prog.funcs
+        .iter()
+        .find(|f| f.pc == prog.start_pc)
+        .map_or_else(String::new, |f| f.name.clone())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::VM;
+    use super::{BufferOutput, FnInput, FnOutput, Program, StrInput, Stream, VM};
     use crate::core::compiler::compile;
+    use crate::core::types::{Code, CodeAddr, ErrKind, Error, MemAddr, Op, Type, Value, WarnKind};
     use crate::utils::load_code_from_file;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn test_vm_basic() {
@@ -161,6 +867,15 @@ mod tests {
         assert_eq!(vec![0, 23], res.unwrap());
     }
 
+    #[test]
+    fn test_vm_assoc() {
+        let code = load_code_from_file("test_cfiles/vm/assoc.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        assert_eq!(vec![5, 2, 6, 2, 1, 200], vm.execute().unwrap());
+    }
+
     #[test]
     fn test_vm_while() {
         let code = load_code_from_file("test_cfiles/vm/while.c");
@@ -182,4 +897,522 @@ mod tests {
         assert!(res.is_ok());
         assert_eq!(vec![10, 8, 6, 4, 2, 0], res.unwrap());
     }
+
+    #[test]
+    fn test_vm_pointer() {
+        let code = load_code_from_file("test_cfiles/vm/pointer.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![2, 1, 8, 9, 3, 30, 100, 18, 5], res.unwrap());
+    }
+
+    #[test]
+    fn test_vm_null_deref() {
+        let code = load_code_from_file("test_cfiles/vm/null_deref.c-");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert_eq!(
+            String::from("InvalidAddress"),
+            format!("{}", res.unwrap_err().kind)
+        );
+    }
+
+    #[test]
+    fn test_vm_struct() {
+        let code = load_code_from_file("test_cfiles/vm/struct.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![24, 3, 5, 2, 3, 9, 7], res.unwrap());
+    }
+
+    #[test]
+    fn test_vm_enum() {
+        let code = load_code_from_file("test_cfiles/vm/enum.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![5, 13, 6, 6, 6], res.unwrap());
+    }
+
+    // the results of a run and all it wrote, values without the prefix
+    fn run_buffered(path: &str) -> (Vec<i32>, String) {
+        let code = load_code_from_file(path);
+        let buf = BufferOutput::new();
+        let mut vm = VM::builder().print_prefix(false).compile(&code).unwrap();
+        vm.set_output(buf.clone());
+        (vm.execute().unwrap(), buf.stdout())
+    }
+
+    #[test]
+    fn test_vm_string() {
+        let (res, out) = run_buffered("test_cfiles/vm/string.c");
+        assert_eq!(vec![5, 104, 6, 0, 3], res);
+        assert_eq!(
+            "5\n104\n6\nhello\nab\tc // no comment\nworld!\n0\nHi\nhello\n3\n",
+            out
+        );
+
+        // chars are bytes, above 127 too
+        let code = "int main() { putchar(200); puts(\"\\xff\\x41\"); }";
+        let buf = BufferOutput::new();
+        let mut vm = VM::builder().compile(code).unwrap();
+        vm.set_output(buf.clone());
+        vm.execute().unwrap();
+        assert_eq!(buf.stdout_bytes(), b"\xc8\xffA\n");
+    }
+
+    #[test]
+    fn test_vm_write_rodata() {
+        let code = load_code_from_file("test_cfiles/vm/write_rodata.c-");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert_eq!(
+            String::from("WriteReadOnly"),
+            format!("{}", res.unwrap_err().kind)
+        );
+    }
+
+    #[test]
+    fn test_vm_const() {
+        let (res, out) = run_buffered("test_cfiles/vm/const.c");
+        assert_eq!(vec![10, 21, 6, 2, 3, 7, 10], res);
+        assert_eq!("10\n21\n6\n2\n3\n7\n10\nconst\n", out);
+    }
+
+    #[test]
+    fn test_vm_static() {
+        let (res, out) = run_buffered("test_cfiles/vm/static.c");
+        assert_eq!(vec![12, 2, 7, 105, 105], res);
+        assert_eq!("12\n2\n7\n105\n105\nstatic\n", out);
+    }
+
+    #[test]
+    fn test_vm_cast() {
+        let code = load_code_from_file("test_cfiles/vm/cast.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let prog = prog.unwrap();
+        assert_eq!(vec![WarnKind::Narrowing; 3], prog.warnings);
+        let mut vm = VM::new(1000, prog);
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![44, 9, -128, 127, 1, 9, 4, 120], res.unwrap());
+    }
+
+    #[test]
+    fn test_vm_sizeof() {
+        let code = load_code_from_file("test_cfiles/vm/sizeof.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![9, 4, 4, 3, 3, 6, 0], res.unwrap());
+    }
+
+    #[test]
+    fn test_vm_func_ptr() {
+        let code = load_code_from_file("test_cfiles/vm/func_ptr.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![1234, 4321, 55, 15, 5, 1, 1, 2], res.unwrap());
+    }
+
+    #[test]
+    fn test_vm_dispatch() {
+        let code = load_code_from_file("test_cfiles/vm/dispatch.c");
+        let mut vm = VM::new(1000, compile(&code).unwrap());
+        let res = vm.execute().unwrap();
+        assert_eq!(vec![13, -1, 42, 2, 1, '*' as i32, 13, 2], res);
+
+        // only funcs are addresses known before running
+        let code = "int x; int *p = &x; int main() {}";
+        assert_eq!(compile(code).err(), Some(ErrKind::GlobalNeedConst));
+        let code = "int f(); int (*g)() = f; int main() {}";
+        assert!(compile(code).is_err());
+        let code = "int a[2] = {1, 2, 3}; int main() {}";
+        assert_eq!(compile(code).err(), Some(ErrKind::TypeErr));
+    }
+
+    #[test]
+    fn test_vm_null_call() {
+        let code = load_code_from_file("test_cfiles/vm/null_call.c-");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert_eq!(
+            String::from("InvalidAddress"),
+            format!("{}", res.unwrap_err().kind)
+        );
+    }
+
+    #[test]
+    fn test_vm_variadic() {
+        let (res, out) = run_buffered("test_cfiles/vm/variadic.c");
+        assert_eq!(vec![6, 60, 104, 34], res);
+        assert_eq!(
+            "6\n60\n104\n42|    7|3   |-0012|-1|4294967295\n34\nff ok str    right% 00a\nno args\n",
+            out
+        );
+
+        // the count is of bytes written, a char above 127 is one
+        let code = "int main() { int n = printf(\"%c%s|%3c\", 233, \"\\xe9\", 200); n; }";
+        let buf = BufferOutput::new();
+        let mut vm = VM::builder().print_prefix(false).compile(code).unwrap();
+        vm.set_output(buf.clone());
+        assert_eq!(vm.execute().unwrap(), vec![6]);
+        assert_eq!(buf.stdout_bytes(), b"\xe9\xe9|  \xc86\n");
+
+        // va_arg stops at the args that were passed, also in a callee
+        let code = "int second(va_list ap) { va_arg(ap, int); return va_arg(ap, int); } \
+                    int pick(int n, ...) { va_list ap; va_start(ap, n); return second(ap); } \
+                    int main() { int x = pick(2, 7, 8); x; pick(1, 7); }";
+        let buf = BufferOutput::new();
+        let mut vm = VM::builder().print_prefix(false).compile(code).unwrap();
+        vm.set_output(buf.clone());
+        assert_eq!(vm.execute().unwrap_err().kind, ErrKind::InvalidAddress);
+        assert_eq!(buf.stdout(), "8\n");
+    }
+
+    #[test]
+    fn test_vm_bad_format() {
+        let code = load_code_from_file("test_cfiles/vm/bad_format.c-");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert_eq!(
+            String::from("BadFormat"),
+            format!("{}", res.unwrap_err().kind)
+        );
+    }
+
+    #[test]
+    fn test_vm_heap() {
+        let code = load_code_from_file("test_cfiles/vm/heap.c");
+        let prog = compile(&code);
+        assert!(prog.is_ok());
+        let mut vm = VM::new(1000, prog.unwrap());
+        let res = vm.execute();
+        assert!(res.is_ok());
+        assert_eq!(vec![4321, 0, 8, 1], res.unwrap());
+        let stats = vm.heap_stats();
+        assert_eq!((stats.allocs, stats.frees), (7, 5));
+        assert_eq!((stats.live_blocks, stats.live_cells), (2, 7));
+        assert_eq!(stats.peak_cells, 8);
+        assert_eq!(vm.heap_leaks().len(), 2);
+    }
+
+    #[test]
+    fn test_vm_heap_errors() {
+        let cases = [
+            ("test_cfiles/vm/double_free.c-", "DoubleFree"),
+            ("test_cfiles/vm/bad_free.c-", "InvalidFree"),
+            ("test_cfiles/vm/use_after_free.c-", "UseAfterFree"),
+        ];
+        for (path, err) in cases.iter() {
+            let code = load_code_from_file(path);
+            let prog = compile(&code);
+            assert!(prog.is_ok());
+            let mut vm = VM::new(1000, prog.unwrap());
+            let res = vm.execute();
+            assert_eq!(String::from(*err), format!("{}", res.unwrap_err().kind));
+        }
+
+        // without a heap the stack still ends at its size
+        let code = load_code_from_file("test_cfiles/vm/recurse.c-");
+        let mut vm = VM::builder().heap_size(0).compile(&code).unwrap();
+        let e = vm.call("deep", &[Value::Int(0)]).unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrKind::StackOverFlow(_, f)) if f == "deep"));
+        let code = "int main() { int *p = malloc(1); p; }";
+        let mut vm = VM::builder().heap_size(0).compile(code).unwrap();
+        assert_eq!(vm.execute().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn test_vm_prototype() {
+        // globals between a prototype and the impl
+        let code = load_code_from_file("test_cfiles/vm/prototype.c");
+        let mut vm = VM::new(1000, compile(&code).unwrap());
+        assert_eq!(vm.execute().unwrap(), vec![12]);
+    }
+
+    #[test]
+    fn test_vm_exit_and_limit() {
+        let code = load_code_from_file("example/gcd.c");
+        let prog = compile(&code).unwrap();
+        let names: Vec<&str> = prog.funcs.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["gcd", "main"]);
+        assert_eq!(prog.func("main").unwrap().pc, prog.start_pc);
+        let mut vm = VM::new(1000, prog);
+        assert_eq!(vm.exit_code(), None);
+        assert_eq!(vm.execute().unwrap(), vec![9]);
+        assert_eq!(vm.exit_code(), Some(0));
+        let n = vm.instructions();
+        assert!(n > 0);
+
+        let prog = compile(&code).unwrap();
+        let mut vm = VM::builder().max_instructions(n).build(prog).unwrap();
+        assert!(vm.execute().is_ok());
+        let prog = compile(&code).unwrap();
+        let mut vm = VM::builder().max_instructions(n - 1).build(prog).unwrap();
+        let e = vm.execute().unwrap_err();
+        assert_eq!(e.kind, ErrKind::InstructionLimit(n - 1));
+
+        let code = load_code_from_file("test_cfiles/vm/forever.c-");
+        let mut vm = VM::builder()
+            .max_instructions(10000)
+            .build(compile(&code).unwrap())
+            .unwrap();
+        let e = vm.execute().unwrap_err();
+        assert_eq!(e.kind, ErrKind::InstructionLimit(10000));
+        assert!(e
+            .to_string()
+            .ends_with("limit reached after 10000 instructions"));
+        assert_eq!(vm.instructions(), 10000);
+
+        let mut vm = VM::builder()
+            .timeout(Duration::from_millis(20))
+            .build(compile(&code).unwrap())
+            .unwrap();
+        match vm.execute().unwrap_err().kind {
+            ErrKind::Timeout(n) => assert_eq!(n, vm.instructions()),
+            e => panic!("{:?}", e),
+        }
+
+        // another thread stops the run
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut vm = VM::builder()
+            .interrupt(flag.clone())
+            .build(compile(&code).unwrap())
+            .unwrap();
+        let run = thread::spawn(move || vm.execute().unwrap_err().kind);
+        thread::sleep(Duration::from_millis(10));
+        flag.store(true, Ordering::Relaxed);
+        assert!(matches!(run.join().unwrap(), ErrKind::Interrupted(n) if n > 0));
+    }
+
+    #[test]
+    fn test_vm_output() {
+        let code = load_code_from_file("test_cfiles/vm/basic.c");
+        let buf = BufferOutput::new();
+        let mut vm = VM::new(1000, compile(&code).unwrap());
+        vm.set_output(buf.clone());
+        vm.execute().unwrap();
+        assert_eq!(buf.stdout(), "Print: 0\nPrint: 23\n");
+        vm.warn("done");
+        assert_eq!(buf.stderr(), "Warning: done\n");
+
+        let code = load_code_from_file("test_cfiles/vm/while.c");
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let mut vm = VM::builder()
+            .print_prefix(false)
+            .build(compile(&code).unwrap())
+            .unwrap();
+        vm.set_output(FnOutput::new(move |stream, s: &[u8]| {
+            sink.lock().unwrap().push((stream, s.to_vec()))
+        }));
+        vm.execute().unwrap();
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], (Stream::Out, b"10\n".to_vec()));
+    }
+
+    #[test]
+    fn test_vm_input() {
+        let code = load_code_from_file("test_cfiles/vm/input.c");
+        let buf = BufferOutput::new();
+        let mut vm = VM::new(1000, compile(&code).unwrap());
+        vm.set_input(StrInput::new("3 4\nx hello\n-12 7;9\n"));
+        vm.set_output(buf.clone());
+        let res = vm.execute().unwrap();
+        assert_eq!(vec![2, 7, 2, 120, -12, 1, 7, 59, 57, -1, -1], res);
+        assert!(buf.stdout().contains("Print: 120\nhello\nPrint: -12\n"));
+
+        // same text a char at a time
+        let mut chunks = "3 4\nx hello\n-12 7;9\n".chars().map(String::from);
+        let mut vm = VM::new(1000, compile(&code).unwrap());
+        vm.set_input(FnInput::new(move || chunks.next()));
+        assert_eq!(vm.execute().unwrap(), res);
+    }
+
+    #[test]
+    fn test_vm_native() {
+        let code = load_code_from_file("test_cfiles/vm/native.c");
+        let spawned = Arc::new(Mutex::new(Vec::new()));
+        let mut vm = VM::builder().empty().unwrap();
+        let log = spawned.clone();
+        vm.register_native("spawn", &[Type::Int, Type::Int], Type::Void, move |args| {
+            log.lock().unwrap().push((args[0], args[1]));
+            Ok(0)
+        });
+        vm.register_native("hp", &[Type::Int], Type::Int, |args| match args[0] {
+            n if n < 0 => Err(format!("no unit {}", n)),
+            n => Ok(n * 10),
+        });
+        vm.register_native("tochar", &[Type::Int], Type::Char, |args| Ok(args[0]));
+        vm.load(&code).unwrap();
+        let mut outs = Vec::new();
+        let e = loop {
+            match vm.execute_once() {
+                Ok(Some(v)) => outs.push(v),
+                Ok(None) => {}
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(outs, vec![50, 97]);
+        assert_eq!(e, ErrKind::NativeErr("no unit -1".to_owned()));
+        assert_eq!(*spawned.lock().unwrap(), vec![(0, 0), (1, 2), (2, 4)]);
+
+        // loading starts over with the same natives
+        vm.load(&code).unwrap();
+        let e = vm.execute().unwrap_err();
+        assert!(e
+            .to_string()
+            .ends_with("native function failed: no unit -1"));
+        assert_eq!(spawned.lock().unwrap().len(), 6);
+
+        // prototypes must match, natives have no code to point to
+        let mut vm = VM::builder().empty().unwrap();
+        vm.register_native("hp", &[Type::Int], Type::Int, |args| Ok(args[0]));
+        let e = vm.load("char hp(int n); int main() {}").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::TypeErr));
+        let e = vm.load("int main() { int (*f)(int) = hp; }").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::TypeErr));
+        let e = vm
+            .load("int hp(int n) { return n; } int main() {}")
+            .unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::ReImpl));
+        assert_eq!(
+            VM::from_source("int main() { hp(1); }")
+                .err()
+                .unwrap()
+                .kind(),
+            Some(ErrKind::NoDeclare)
+        );
+        vm.load("int hp(int n); int main() { int a = hp(4) + 1; a; }")
+            .unwrap();
+        assert_eq!(vm.execute().unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn test_vm_call() {
+        let code = load_code_from_file("test_cfiles/vm/call.c");
+        let mut vm = VM::from_source(&code).unwrap();
+        let args = [Value::Int(99), Value::Int(90)];
+        assert_eq!(vm.call("gcd", &args).unwrap(), Value::Int(9));
+        assert_eq!(vm.call("gcd", &args).unwrap(), Value::Int(9));
+        // globals live on between calls, a failed one leaves the vm usable
+        assert_eq!(vm.call("count", &[]).unwrap(), Value::Int(6));
+        assert_eq!(vm.call("reset", &[]).unwrap(), Value::Void);
+        vm.call("gcd", &[Value::Int(4), Value::Int(2)]).unwrap();
+        assert_eq!(vm.call("count", &[]).unwrap(), Value::Int(2));
+        let e = vm.call("div", &[Value::Int(1), Value::Int(0)]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::DivideZero));
+        let res = vm.call("div", &[Value::Int(7), Value::Int(2)]).unwrap();
+        assert_eq!(res, Value::Int(3));
+        assert_eq!(
+            vm.call("upper", &[Value::Int(353)]).unwrap(),
+            Value::Int(65)
+        );
+
+        let kind = |e: Error| e.kind().unwrap();
+        assert_eq!(kind(vm.call("lcm", &[]).unwrap_err()), ErrKind::NoDeclare);
+        assert_eq!(kind(vm.call("puts", &[]).unwrap_err()), ErrKind::NoDeclare);
+        let e = vm.call("gcd", &[Value::Int(1)]).unwrap_err();
+        assert_eq!(kind(e), ErrKind::TypeErr);
+        let e = vm.call("gcd", &[Value::Int(1), Value::Void]).unwrap_err();
+        assert_eq!(kind(e), ErrKind::TypeErr);
+        let e = vm.call("sum", &[Value::Int(1)]).unwrap_err();
+        assert_eq!(kind(e), ErrKind::TypeErr);
+
+        // the budget is per call
+        vm.call("gcd", &args).unwrap();
+        let n = vm.instructions();
+        let mut vm = VM::builder().max_instructions(n).compile(&code).unwrap();
+        for _ in 0..50 {
+            assert_eq!(vm.call("gcd", &args).unwrap(), Value::Int(9));
+        }
+        let mut vm = VM::builder()
+            .max_instructions(n - 1)
+            .compile(&code)
+            .unwrap();
+        let e = vm.call("gcd", &args).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::InstructionLimit(n - 1)));
+    }
+
+    #[test]
+    fn test_vm_stack_bounds() {
+        let code = load_code_from_file("test_cfiles/vm/recurse.c-");
+        let mut vm = VM::from_source(&code).unwrap();
+        let e = vm.execute().unwrap_err();
+        assert_eq!(e.kind, ErrKind::StackOverFlow(10001, "down".to_owned()));
+        assert!(e
+            .to_string()
+            .ends_with("stack overflow at call depth 10001 in down"));
+
+        // the data stack runs out first, in the function pushing
+        let e = vm.call("deep", &[Value::Int(0)]).unwrap_err();
+        match e.kind() {
+            Some(ErrKind::StackOverFlow(depth, func)) => {
+                assert!(depth > 100 && depth < 10000);
+                assert_eq!(func, "deep");
+            }
+            e => panic!("{:?}", e),
+        }
+
+        let mut vm = VM::builder().max_call_depth(50).compile(&code).unwrap();
+        let e = vm.execute().unwrap_err();
+        assert_eq!(e.kind, ErrKind::StackOverFlow(51, "down".to_owned()));
+        let e = vm.call("deep", &[Value::Int(0)]).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrKind::StackOverFlow(51, "deep".to_owned()))
+        );
+
+        // codes that pop more than was pushed or reach outside the vm
+        let fails = |codes: Vec<Code>| {
+            let mut prog = Program::empty();
+            prog.start_pc = prog.codes.len();
+            prog.codes.extend(codes);
+            VM::new(200, prog).execute().unwrap_err().kind
+        };
+        assert_eq!(fails(vec![Code::Pop(1)]), ErrKind::StackUnderflow);
+        let codes = vec![Code::PushValue(1), Code::Op(Op::Add)];
+        assert_eq!(fails(codes), ErrKind::StackUnderflow);
+        assert_eq!(fails(vec![Code::Ret(1)]), ErrKind::StackUnderflow);
+        let codes = vec![Code::Call(CodeAddr::Direct(2), 3), Code::Ret(0)];
+        assert_eq!(fails(codes), ErrKind::StackUnderflow);
+        let bad_addr = [
+            vec![Code::Jump(CodeAddr::Direct(9))],
+            vec![Code::Jump(CodeAddr::NameStart(3))],
+            vec![Code::Push(MemAddr::Indirect(100000))],
+            vec![Code::PushValue(1), Code::PopMov(MemAddr::Indirect(100000))],
+            vec![Code::PushValue(1), Code::Load(usize::MAX)],
+        ];
+        for codes in bad_addr.iter() {
+            assert_eq!(fails(codes.clone()), ErrKind::InvalidAddress);
+        }
+        let codes = vec![Code::PushValue(1), Code::Op(Op::Deref)];
+        assert_eq!(fails(codes), ErrKind::InvalidCode);
+        let codes = vec![Code::PushValue(1), Code::Trunc(0)];
+        assert_eq!(fails(codes), ErrKind::InvalidCode);
+    }
 }
@@ -0,0 +1,109 @@
+use super::{check_fits, DEFAULT_HEAP_SIZE, DEFAULT_MAX_CALL_DEPTH, VM};
+use crate::core::compiler::{compile_source, Program};
+use crate::core::repl::Repl;
+use crate::core::types::Error;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_STACK_SIZE: usize = 1000;
+
+// Options of a vm, sizes are in cells
+#[derive(Debug, Clone)]
+pub struct VmBuilder {
+    stack_size: usize,
+    heap_size: usize,
+    max_instructions: Option<u64>,
+    max_call_depth: usize,
+    timeout: Option<Duration>,
+    interrupt: Option<Arc<AtomicBool>>,
+    print_prefix: bool,
+}
+
+impl Default for VmBuilder {
+    fn default() -> Self {
+        VmBuilder {
+            stack_size: DEFAULT_STACK_SIZE,
+            heap_size: DEFAULT_HEAP_SIZE,
+            max_instructions: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            timeout: None,
+            interrupt: None,
+            print_prefix: true,
+        }
+    }
+}
+
+impl VmBuilder {
+    pub fn new() -> Self {
+        VmBuilder::default()
+    }
+
+    // globals and string literals take their room from the stack
+    pub fn stack_size(mut self, n: usize) -> Self {
+        self.stack_size = n;
+        self
+    }
+
+    pub fn heap_size(mut self, n: usize) -> Self {
+        self.heap_size = n;
+        self
+    }
+
+    // each execute (and call) fails with InstructionLimit past n codes,
+    // unlimited by default
+    pub fn max_instructions(mut self, n: u64) -> Self {
+        self.max_instructions = Some(n);
+        self
+    }
+
+    // calls nested deeper fail with StackOverFlow, 10000 by default
+    pub fn max_call_depth(mut self, n: usize) -> Self {
+        self.max_call_depth = n;
+        self
+    }
+
+    // each execute (and call) fails with Timeout after `t`
+    pub fn timeout(mut self, t: Duration) -> Self {
+        self.timeout = Some(t);
+        self
+    }
+
+    // execute fails with Interrupted once the flag is set, see VM::set_interrupt
+    pub fn interrupt(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.interrupt = Some(flag);
+        self
+    }
+
+    // print statements write `Print: 3`, or `3` without the prefix
+    pub fn print_prefix(mut self, on: bool) -> Self {
+        self.print_prefix = on;
+        self
+    }
+
+    // fails with StackOverFlow when the stack can't hold the memory
+    pub fn build(&self, prog: Program) -> Result<VM, Error> {
+        check_fits(self.stack_size, &prog)?;
+        let mut vm = VM::with_heap(self.stack_size, self.heap_size, prog);
+        vm.max_instructions = self.max_instructions;
+        vm.max_call_depth = self.max_call_depth;
+        vm.timeout = self.timeout;
+        vm.interrupt = self.interrupt.clone();
+        vm.print_prefix = self.print_prefix;
+        Ok(vm)
+    }
+
+    // no program yet, natives are registered before VM::load
+    pub fn empty(&self) -> Result<VM, Error> {
+        self.build(Program::empty())
+    }
+
+    pub fn compile(&self, code: &str) -> Result<VM, Error> {
+        self.build(compile_source(code)?)
+    }
+
+    // a session whose inputs run on a vm of these options
+    pub fn repl(&self) -> Result<Repl, Error> {
+        Repl::with_vm(self)
+    }
+}
@@ -0,0 +1,114 @@
+use crate::core::types::ErrKind;
+use std::collections::BTreeMap;
+
+// sizes are in cells, the unit sizeof counts in, not bytes
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct HeapStats {
+    pub live_blocks: usize,
+    pub live_cells: usize,
+    pub peak_cells: usize,
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+// first fit allocator over [start, end) of the vm memory
+pub struct Heap {
+    start: usize,
+    end: usize,
+    blocks: BTreeMap<usize, usize>, // live, addr -> size
+    freed: BTreeMap<usize, usize>,  // released and not reused yet
+    stats: HeapStats,
+}
+
+impl Heap {
+    pub fn new(start: usize, size: usize) -> Self {
+        Heap {
+            start,
+            end: start + size,
+            blocks: BTreeMap::new(),
+            freed: BTreeMap::new(),
+            stats: HeapStats::default(),
+        }
+    }
+
+    // the data stack ends here
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr < self.end
+    }
+
+    // None when out of memory
+    pub fn alloc(&mut self, sz: usize) -> Option<usize> {
+        let mut cur = self.start;
+        for (a, n) in self.blocks.iter() {
+            if a - cur >= sz {
+                break;
+            }
+            cur = a + n;
+        }
+        if cur + sz > self.end {
+            return None;
+        }
+        // reused space is not freed memory anymore
+        let stale: Vec<usize> = self
+            .freed
+            .range(..cur + sz)
+            .filter(|(a, n)| *a + *n > cur)
+            .map(|(a, _)| *a)
+            .collect();
+        for a in stale {
+            self.freed.remove(&a);
+        }
+        self.blocks.insert(cur, sz);
+        self.stats.allocs += 1;
+        self.stats.live_blocks += 1;
+        self.stats.live_cells += sz;
+        self.stats.peak_cells = self.stats.peak_cells.max(self.stats.live_cells);
+        Some(cur)
+    }
+
+    // size of the released block
+    pub fn free(&mut self, addr: usize) -> Result<usize, ErrKind> {
+        match self.blocks.remove(&addr) {
+            Some(sz) => {
+                self.freed.insert(addr, sz);
+                self.stats.frees += 1;
+                self.stats.live_blocks -= 1;
+                self.stats.live_cells -= sz;
+                Ok(sz)
+            }
+            None if self.freed.contains_key(&addr) => Err(ErrKind::DoubleFree),
+            None => Err(ErrKind::InvalidFree),
+        }
+    }
+
+    pub fn block_size(&self, addr: usize) -> Option<usize> {
+        self.blocks.get(&addr).copied()
+    }
+
+    // [addr, addr + sz) must be inside one live block
+    pub fn check(&self, addr: usize, sz: usize) -> Result<(), ErrKind> {
+        let hit = |m: &BTreeMap<usize, usize>| match m.range(..=addr).next_back() {
+            Some((a, n)) => addr < a + n,
+            None => false,
+        };
+        match self.blocks.range(..=addr).next_back() {
+            Some((a, n)) if addr + sz <= a + n => Ok(()),
+            _ if hit(&self.blocks) => Err(ErrKind::InvalidAddress),
+            _ if hit(&self.freed) => Err(ErrKind::UseAfterFree),
+            _ => Err(ErrKind::InvalidAddress),
+        }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats.clone()
+    }
+
+    // blocks never freed, (addr, size)
+    pub fn leaks(&self) -> Vec<(usize, usize)> {
+        self.blocks.iter().map(|(a, n)| (*a, *n)).collect()
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{self, BufRead};
+
+// Where a vm reads from, see VM::set_input
+pub trait Input: Send {
+    // next chunk of text, None at the end
+    fn fill(&mut self) -> Option<String>;
+}
+
+// the process' stdin, a line at a time, the default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdInput;
+
+impl Input for StdInput {
+    fn fill(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+}
+
+// all of the text up front
+#[derive(Debug, Clone)]
+pub struct StrInput {
+    text: Option<String>,
+}
+
+impl StrInput {
+    pub fn new(text: &str) -> Self {
+        StrInput {
+            text: Some(text.to_owned()),
+        }
+    }
+}
+
+impl Input for StrInput {
+    fn fill(&mut self) -> Option<String> {
+        self.text.take()
+    }
+}
+
+// asks `f` whenever the text read so far runs out
+pub struct FnInput<F: FnMut() -> Option<String> + Send> {
+    f: F,
+}
+
+impl<F: FnMut() -> Option<String> + Send> FnInput<F> {
+    pub fn new(f: F) -> Self {
+        FnInput { f }
+    }
+}
+
+impl<F: FnMut() -> Option<String> + Send> Input for FnInput<F> {
+    fn fill(&mut self) -> Option<String> {
+        (self.f)()
+    }
+}
+
+// Bytes of an input with one of lookahead, as scanf needs
+pub struct Reader {
+    input: Box<dyn Input>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Reader {
+    pub fn new(input: Box<dyn Input>) -> Self {
+        Reader {
+            input,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub fn peek(&mut self) -> Option<u8> {
+        while self.pos == self.buf.len() {
+            self.buf = self.input.fill()?.into_bytes();
+            self.pos = 0;
+        }
+        Some(self.buf[self.pos])
+    }
+
+    pub fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    // false at the end
+    pub fn skip_space(&mut self) -> bool {
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_whitespace() {
+                return true;
+            }
+            self.pos += 1;
+        }
+        false
+    }
+
+    // optional sign then digits, None if there are no digits
+    pub fn int(&mut self) -> Option<i32> {
+        let neg = match self.peek()? {
+            c @ (b'-' | b'+') => {
+                self.pos += 1;
+                c == b'-'
+            }
+            _ => false,
+        };
+        let mut v: Option<i32> = None;
+        while let Some(d) = self.peek().filter(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            let n = v.unwrap_or(0);
+            v = Some(n.wrapping_mul(10).wrapping_add((d - b'0') as i32));
+        }
+        v.map(|n| if neg { n.wrapping_neg() } else { n })
+    }
+
+    // up to the next white space
+    pub fn word(&mut self) -> Vec<u8> {
+        let mut w = Vec::new();
+        while let Some(c) = self.peek().filter(|c| !c.is_ascii_whitespace()) {
+            self.pos += 1;
+            w.push(c);
+        }
+        w
+    }
+}
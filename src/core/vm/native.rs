@@ -0,0 +1,35 @@
+use crate::core::types::{calc_trunc, ErrKind, Type};
+
+pub type NativeFn = Box<dyn FnMut(&[i32]) -> Result<i32, String> + Send>;
+
+// A function of the host, see VM::register_native
+pub struct Native {
+    pub name: String,
+    pub ty: Type, // Type::Func of the params then the result
+    f: NativeFn,
+}
+
+impl Native {
+    pub fn new(name: &str, params: &[Type], ret: Type, f: NativeFn) -> Self {
+        let mut sig = params.to_vec();
+        sig.push(ret);
+        Native {
+            name: name.to_owned(),
+            ty: Type::Func(sig, false),
+            f,
+        }
+    }
+
+    // the cell to push, None for void
+    pub fn call(&mut self, args: &[i32]) -> Result<Option<i32>, ErrKind> {
+        let v = (self.f)(args).map_err(ErrKind::NativeErr)?;
+        match &self.ty {
+            Type::Func(sig, _) => match sig.last().unwrap().unqualified() {
+                Type::Void => Ok(None),
+                Type::Char => Ok(Some(calc_trunc(v, 8))),
+                _ => Ok(Some(v)),
+            },
+            _ => panic!("Not func type"),
+        }
+    }
+}
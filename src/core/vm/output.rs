@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stream {
+    Out, // what the program prints
+    Err, // warnings of a run
+}
+
+// Where a vm writes, see VM::set_output. Chars of the program are bytes,
+// not always utf-8
+pub trait Output: Send {
+    fn write(&mut self, stream: Stream, s: &[u8]);
+}
+
+// the process' stdout and stderr, the default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdOutput;
+
+impl Output for StdOutput {
+    fn write(&mut self, stream: Stream, s: &[u8]) {
+        // a closed pipe is not the program's failure
+        let _ = match stream {
+            Stream::Out => io::stdout().write_all(s),
+            Stream::Err => io::stderr().write_all(s),
+        };
+    }
+}
+
+// In memory, clones share the text so one can be kept to read it
+#[derive(Debug, Default, Clone)]
+pub struct BufferOutput {
+    out: Arc<Mutex<Vec<u8>>>,
+    err: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BufferOutput {
+    pub fn new() -> Self {
+        BufferOutput::default()
+    }
+
+    // bytes that aren't utf-8 read as U+FFFD
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.out.lock().unwrap()).into_owned()
+    }
+
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.err.lock().unwrap()).into_owned()
+    }
+
+    pub fn stdout_bytes(&self) -> Vec<u8> {
+        self.out.lock().unwrap().clone()
+    }
+}
+
+impl Output for BufferOutput {
+    fn write(&mut self, stream: Stream, s: &[u8]) {
+        match stream {
+            Stream::Out => self.out.lock().unwrap().extend_from_slice(s),
+            Stream::Err => self.err.lock().unwrap().extend_from_slice(s),
+        }
+    }
+}
+
+// each write goes to `f`
+pub struct FnOutput<F: FnMut(Stream, &[u8]) + Send> {
+    f: F,
+}
+
+impl<F: FnMut(Stream, &[u8]) + Send> FnOutput<F> {
+    pub fn new(f: F) -> Self {
+        FnOutput { f }
+    }
+}
+
+impl<F: FnMut(Stream, &[u8]) + Send> Output for FnOutput<F> {
+    fn write(&mut self, stream: Stream, s: &[u8]) {
+        (self.f)(stream, s)
+    }
+}
@@ -1,31 +1,140 @@
 mod core;
 mod utils;
 
-use crate::core::{compile, Code, ErrKind, VM};
-use crate::utils::load_code_from_file;
+use crate::core::{analyze_root, lex_source, parse_source};
+use crate::utils::read_code;
 
-pub fn compile_and_run(path: &str) -> Result<Vec<i32>, ErrKind> {
-    let code = load_code_from_file(path);
-    let prog = compile(&code)?;
-    let mut vm = VM::new(1000, prog);
-    vm.execute()
+pub use crate::core::nodes::RootNd;
+#[cfg(feature = "serde")]
+pub use crate::core::{ast_json, program_json, semantic_json, to_json, tokens_json};
+pub use crate::core::{compile_root, format, nodes, visit, Print};
+pub use crate::core::{BufferOutput, FnOutput, Output, StdOutput, Stream};
+pub use crate::core::{
+    Builtin, CalcInfo, CalcItem, Code, CodeAddr, ErrKind, Error, FieldInfo, FuncInfo, FuncSymbol,
+    HeapStats, IoError, Layout, LexError, MemAddr, NodeId, NodeTable, Op, ParseError, Program,
+    Repl, RuntimeError, ScopeInfo, Semantic, SemanticError, StorageClass, StructInfo, Token, Type,
+    Value, VarInfo, VmBuilder, WarnKind, VM,
+};
+pub use crate::core::{FnInput, Input, StdInput, StrInput};
+
+// Each stage takes source from memory, `//` comments are allowed
+pub fn lex(code: &str) -> Result<Vec<Token>, Error> {
+    Ok(lex_source(code)?)
+}
+
+pub fn parse(code: &str) -> Result<RootNd, Error> {
+    parse_source(code)
+}
+
+// results are kept in the tables of Semantic, the tree is not changed
+pub fn analyze(nd: &RootNd) -> Result<Semantic, Error> {
+    Ok(analyze_root(nd).map_err(|kind| SemanticError { kind })?)
 }
 
-pub fn compile_to_code(path: &str) -> Result<Vec<Code>, ErrKind> {
-    let code = load_code_from_file(path);
+pub fn compile(code: &str) -> Result<Program, Error> {
+    crate::core::compile_source(code)
+}
+
+pub fn compile_and_run(path: &str) -> Result<Vec<i32>, Error> {
+    let code = read_code(path)?;
     let prog = compile(&code)?;
-    for c in prog.codes.iter() {
-        println!("{:?}", c.clone());
+    let warnings = prog.warnings.clone();
+    let mut vm = VmBuilder::new().build(prog)?;
+    for w in warnings.iter() {
+        vm.warn(&w.to_string());
+    }
+    let res = vm.execute()?;
+    let leaks = vm.heap_leaks();
+    if !leaks.is_empty() {
+        let cells: usize = leaks.iter().map(|(_, n)| n).sum();
+        vm.warn(&format!(
+            "{} heap blocks ({} cells) leaked",
+            leaks.len(),
+            cells
+        ));
     }
-    Ok(prog.codes)
+    Ok(res)
+}
+
+// codes of the program in a file, `rscmm disasm` prints them
+pub fn compile_to_code(path: &str) -> Result<Vec<Code>, Error> {
+    let code = read_code(path)?;
+    Ok(compile(&code)?.codes)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::compile_and_run;
+    use super::{analyze, compile, compile_and_run, compile_root, lex, parse, ErrKind, Token, VM};
+    use std::error::Error as _;
 
     #[test]
     fn gcd_example() {
         assert_eq!(vec![9], compile_and_run("example/gcd.c").unwrap());
     }
+
+    #[test]
+    fn test_staged_api() {
+        let code = "int sq(int x) { return x * x; } // squares\nint main() { int a = sq(7); a; }";
+        let tks = lex(code).unwrap();
+        assert_eq!(tks[0], Token::Type(super::Type::Int));
+        let nd = parse(code).unwrap();
+        let info = analyze(&nd).unwrap();
+        let mut vm = VM::builder()
+            .stack_size(200)
+            .build(compile_root(&nd, info).unwrap())
+            .unwrap();
+        assert_eq!(vm.execute().unwrap(), vec![49]);
+        // the tables of one tree don't fit another, even of the same source
+        let other = parse(code).unwrap();
+        let e = compile_root(&other, analyze(&nd).unwrap()).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrKind::ForeignTree));
+
+        let mut vm = VM::from_source(code).unwrap();
+        assert_eq!(vm.execute().unwrap(), vec![49]);
+        let prog = compile(code).unwrap();
+        assert!(prog.warnings.is_empty());
+
+        assert_eq!(
+            lex("int s = \"a").unwrap_err().kind(),
+            Some(ErrKind::LexErr)
+        );
+        assert_eq!(
+            parse("int main( {").unwrap_err().kind(),
+            Some(ErrKind::ParseErr)
+        );
+        let nd = parse("int f();").unwrap();
+        assert_eq!(analyze(&nd).unwrap_err().kind(), Some(ErrKind::NoMainFunc));
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = lex("int a;\nint b = 1;\nint c = 'ab';").unwrap_err();
+        assert_eq!(e.to_string(), "lex error at line 3: invalid token");
+        let e = parse("int a;\nint main() {\n  a = 1 +;\n}").unwrap_err();
+        assert_eq!(e.to_string(), "parse error at line 3: unexpected `;`");
+        let e = compile("int main() { x; }").unwrap_err();
+        assert_eq!(e.to_string(), "semantic error: use of an undeclared name");
+        assert!(e.source().is_none());
+
+        let e = VM::from_source("int main() { int a = 1 / 0; }")
+            .unwrap()
+            .execute()
+            .unwrap_err();
+        assert_eq!(e.kind, ErrKind::DivideZero);
+        assert!(e.to_string().ends_with("division by zero"));
+        let e = VM::builder().stack_size(10).compile("int main() {}");
+        let kind = ErrKind::StackOverFlow(0, String::from("main"));
+        assert_eq!(e.err().unwrap().kind(), Some(kind));
+
+        // the os error is the source, not part of the message
+        let e = compile_and_run("example/missing.c").unwrap_err();
+        assert_eq!(e.to_string(), "cannot read example/missing.c");
+        let io = e
+            .source()
+            .unwrap()
+            .downcast_ref::<std::io::Error>()
+            .unwrap();
+        assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+        assert!(io.source().is_none());
+    }
 }
@@ -0,0 +1,434 @@
+use rscmm::{Error, IoError, Program, VmBuilder, WarnKind};
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: rscmm <command> [options] <file.c>
+       rscmm repl [options]
+
+commands:
+  run       run the program, exits with main's return value
+  check     analyze only and print diagnostics
+  disasm    print the codes with function labels
+  tokens    dump the tokens
+  ast       dump the tree
+  repl      read and run definitions, statements and expressions from stdin
+
+options:
+  --stack-size <cells>        data stack of the vm (1000)
+  --heap-size <cells>         heap of the vm (4096)
+  --max-instructions <n>      stop a run after n codes
+  --timeout-ms <ms>           stop the vm after ms milliseconds
+  --max-call-depth <n>        calls nested deeper overflow (10000)
+  --no-print-prefix           print statements write `3`, not `Print: 3`
+  --diagnostics <human|json>  format of errors and warnings (human)
+  --json                      dump tokens or ast as JSON, needs the serde feature";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Command {
+    Run,
+    Check,
+    Disasm,
+    Tokens,
+    Ast,
+    Repl,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Format {
+    Human,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    cmd: Command,
+    path: String,
+    stack_size: Option<usize>,
+    heap_size: Option<usize>,
+    max_instructions: Option<u64>,
+    timeout_ms: Option<u64>,
+    max_call_depth: Option<usize>,
+    print_prefix: bool,
+    diagnostics: Format,
+    json: bool,
+}
+
+fn parse_num<T: std::str::FromStr>(flag: &str, v: Option<&String>) -> Result<T, String> {
+    let v = v.ok_or(format!("{} needs a value", flag))?;
+    v.parse()
+        .map_err(|_| format!("{} takes a number, got `{}`", flag, v))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let cmd = match args.first().map(|s| s.as_str()) {
+        Some("run") => Command::Run,
+        Some("check") => Command::Check,
+        Some("disasm") => Command::Disasm,
+        Some("tokens") => Command::Tokens,
+        Some("ast") => Command::Ast,
+        Some("repl") => Command::Repl,
+        Some(c) => return Err(format!("unknown command `{}`", c)),
+        None => return Err(String::from("missing command")),
+    };
+    let mut opts = Options {
+        cmd,
+        path: String::new(),
+        stack_size: None,
+        heap_size: None,
+        max_instructions: None,
+        timeout_ms: None,
+        max_call_depth: None,
+        print_prefix: true,
+        diagnostics: Format::Human,
+        json: false,
+    };
+    let mut it = args[1..].iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--stack-size" => opts.stack_size = Some(parse_num(a, it.next())?),
+            "--heap-size" => opts.heap_size = Some(parse_num(a, it.next())?),
+            "--max-instructions" => opts.max_instructions = Some(parse_num(a, it.next())?),
+            "--timeout-ms" => opts.timeout_ms = Some(parse_num(a, it.next())?),
+            "--max-call-depth" => opts.max_call_depth = Some(parse_num(a, it.next())?),
+            "--diagnostics" => {
+                opts.diagnostics = match it.next().map(|s| s.as_str()) {
+                    Some("human") => Format::Human,
+                    Some("json") => Format::Json,
+                    _ => return Err(String::from("--diagnostics takes human or json")),
+                }
+            }
+            "--no-print-prefix" => opts.print_prefix = false,
+            "--json" => opts.json = true,
+            f if f.starts_with("--") => return Err(format!("unknown option `{}`", f)),
+            p if opts.path.is_empty() => opts.path = p.to_owned(),
+            p => return Err(format!("unexpected argument `{}`", p)),
+        }
+    }
+    if opts.cmd == Command::Repl && !opts.path.is_empty() {
+        return Err(String::from("repl reads stdin, not a file"));
+    } else if opts.cmd != Command::Repl && opts.path.is_empty() {
+        return Err(String::from("missing source file"));
+    }
+    Ok(opts)
+}
+
+// an error or warning about the source file
+struct Diagnostic {
+    severity: &'static str,
+    kind: String,
+    line: Option<usize>,
+    message: String,
+}
+
+impl Diagnostic {
+    fn error(e: &Error) -> Self {
+        let line = match e {
+            Error::Lex(e) => Some(e.line),
+            Error::Parse(e) => Some(e.line),
+            _ => None,
+        };
+        // with its causes, "cannot read a.c: No such file or directory"
+        let mut message = e.to_string();
+        let mut cause = std::error::Error::source(e);
+        while let Some(c) = cause {
+            message = format!("{}: {}", message, c);
+            cause = c.source();
+        }
+        Diagnostic {
+            severity: "error",
+            kind: e.kind().map_or(String::from("Io"), |k| k.to_string()),
+            line,
+            message,
+        }
+    }
+
+    fn warning(kind: String, message: String) -> Self {
+        Diagnostic {
+            severity: "warning",
+            kind,
+            line: None,
+            message,
+        }
+    }
+
+    fn render(&self, path: &str, format: Format) -> String {
+        match format {
+            Format::Human if self.severity == "error" => format!("{}: {}", path, self.message),
+            Format::Human => format!("{}: warning: {}", path, self.message),
+            Format::Json => format!(
+                "{{\"file\":{},\"severity\":\"{}\",\"kind\":{},\"line\":{},\"message\":{}}}",
+                json_str(path),
+                self.severity,
+                json_str(&self.kind),
+                self.line.map_or(String::from("null"), |l| l.to_string()),
+                json_str(&self.message)
+            ),
+        }
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn warnings(ws: &[WarnKind]) -> Vec<Diagnostic> {
+    ws.iter()
+        .map(|w| Diagnostic::warning(w.to_string(), w.describe().to_owned()))
+        .collect()
+}
+
+fn disasm(prog: &Program) {
+    let mut labels = prog.funcs.iter().peekable();
+    for (pc, c) in prog.codes.iter().enumerate() {
+        while let Some(f) = labels.next_if(|f| f.pc == pc) {
+            println!("{}:", f.name);
+        }
+        println!("{:>6}  {:?}", pc, c);
+    }
+}
+
+#[cfg(feature = "serde")]
+fn dump_json(cmd: Command, code: &str) -> Result<String, Error> {
+    match cmd {
+        Command::Tokens => rscmm::tokens_json(code),
+        _ => rscmm::ast_json(code),
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn dump_json(_cmd: Command, _code: &str) -> Result<String, Error> {
+    eprintln!("rscmm: --json needs rscmm built with the serde feature");
+    process::exit(2);
+}
+
+fn vm_options(opts: &Options) -> VmBuilder {
+    let mut builder = VmBuilder::new().print_prefix(opts.print_prefix);
+    if let Some(n) = opts.stack_size {
+        builder = builder.stack_size(n);
+    }
+    if let Some(n) = opts.heap_size {
+        builder = builder.heap_size(n);
+    }
+    if let Some(n) = opts.max_instructions {
+        builder = builder.max_instructions(n);
+    }
+    if let Some(n) = opts.max_call_depth {
+        builder = builder.max_call_depth(n);
+    }
+    if let Some(ms) = opts.timeout_ms {
+        builder = builder.timeout(Duration::from_millis(ms));
+    }
+    builder
+}
+
+// an input goes on while a brace or paren is open
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in input.chars() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{' | '(') => depth += 1,
+            (None, '}' | ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+fn repl(opts: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = vm_options(opts).repl()?;
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        input.push_str(&line);
+        if !is_complete(&input) {
+            continue;
+        }
+        let mut diags = Vec::new();
+        match repl.eval(&input) {
+            Ok(Some(v)) => println!("{}", v),
+            Ok(None) => {}
+            Err(e) => diags.push(Diagnostic::error(&e)),
+        }
+        diags.extend(warnings(&repl.take_warnings()));
+        for d in diags.iter() {
+            eprintln!("{}", d.render("<stdin>", opts.diagnostics));
+        }
+        input.clear();
+    }
+}
+
+// diagnostics to report, and the exit code
+fn run(opts: &Options, code: &str) -> Result<(Vec<Diagnostic>, i32), Error> {
+    match opts.cmd {
+        Command::Tokens | Command::Ast if opts.json => println!("{}", dump_json(opts.cmd, code)?),
+        Command::Tokens => {
+            for t in rscmm::lex(code)? {
+                println!("{:?}", t);
+            }
+        }
+        Command::Ast => println!("{:#?}", rscmm::parse(code)?),
+        Command::Check => {
+            let info = rscmm::analyze(&rscmm::parse(code)?)?;
+            return Ok((warnings(&info.warnings), 0));
+        }
+        Command::Disasm => {
+            let prog = rscmm::compile(code)?;
+            disasm(&prog);
+            return Ok((warnings(&prog.warnings), 0));
+        }
+        Command::Repl => unreachable!("repl has no file"),
+        Command::Run => {
+            let prog = rscmm::compile(code)?;
+            let mut diags = warnings(&prog.warnings);
+            let mut vm = vm_options(opts).build(prog)?;
+            vm.execute()?;
+            let leaks = vm.heap_leaks();
+            if !leaks.is_empty() {
+                let cells: usize = leaks.iter().map(|(_, n)| n).sum();
+                let msg = format!("{} heap blocks ({} cells) leaked", leaks.len(), cells);
+                diags.push(Diagnostic::warning(String::from("Leak"), msg));
+            }
+            return Ok((diags, vm.exit_code().unwrap_or(0)));
+        }
+    }
+    Ok((Vec::new(), 0))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            eprintln!("rscmm: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+    if opts.cmd == Command::Repl {
+        if let Err(e) = repl(&opts) {
+            eprintln!("rscmm: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let res = fs::read_to_string(&opts.path)
+        .map_err(|source| {
+            Error::from(IoError {
+                path: opts.path.clone(),
+                source,
+            })
+        })
+        .and_then(|code| run(&opts, &code));
+    let (diags, exit) = match res {
+        Ok(r) => r,
+        Err(e) => (vec![Diagnostic::error(&e)], 1),
+    };
+    for d in diags.iter() {
+        let line = d.render(&opts.path, opts.diagnostics);
+        // diagnostics are the output of check
+        if opts.cmd == Command::Check {
+            println!("{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+    process::exit(exit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_complete, json_str, parse_args, Command, Diagnostic, Format};
+    use rscmm::ErrKind;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let opts = parse_args(&args("run a.c --stack-size 2000 --max-instructions 50")).unwrap();
+        assert_eq!(opts.cmd, Command::Run);
+        assert_eq!(opts.path, "a.c");
+        assert_eq!(opts.stack_size, Some(2000));
+        assert_eq!(opts.max_instructions, Some(50));
+        assert_eq!(opts.timeout_ms, None);
+        let opts = parse_args(&args("run a.c --timeout-ms 300 --max-call-depth 64")).unwrap();
+        assert_eq!(opts.timeout_ms, Some(300));
+        assert_eq!(opts.max_call_depth, Some(64));
+        assert_eq!(opts.diagnostics, Format::Human);
+        assert!(opts.print_prefix);
+        assert!(
+            !parse_args(&args("run --no-print-prefix a.c"))
+                .unwrap()
+                .print_prefix
+        );
+        let opts = parse_args(&args("check --diagnostics json a.c")).unwrap();
+        assert_eq!((opts.cmd, opts.diagnostics), (Command::Check, Format::Json));
+
+        assert!(parse_args(&args("")).is_err());
+        assert!(parse_args(&args("build a.c")).is_err());
+        assert!(parse_args(&args("run")).is_err());
+        assert!(parse_args(&args("run a.c b.c")).is_err());
+        assert!(parse_args(&args("run a.c --stack-size")).is_err());
+        assert!(parse_args(&args("run a.c --heap-size lots")).is_err());
+        assert!(parse_args(&args("run a.c --diagnostics xml")).is_err());
+        assert_eq!(parse_args(&args("repl")).unwrap().cmd, Command::Repl);
+        assert!(parse_args(&args("repl a.c")).is_err());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let e = rscmm::parse("int a;\nint main( {").unwrap_err();
+        let d = Diagnostic::error(&e);
+        assert_eq!(d.kind, ErrKind::ParseErr.to_string());
+        assert_eq!(
+            d.render("a.c", Format::Human),
+            "a.c: parse error at line 2: unexpected `{`"
+        );
+        assert_eq!(
+            d.render("a.c", Format::Json),
+            "{\"file\":\"a.c\",\"severity\":\"error\",\"kind\":\"ParseErr\",\"line\":2,\
+             \"message\":\"parse error at line 2: unexpected `{`\"}"
+        );
+        assert_eq!(json_str("a\"b\\\n\u{1}"), "\"a\\\"b\\\\\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("int a = 1;"));
+        assert!(!is_complete("int f() {\n"));
+        assert!(is_complete("int f() {\n  return 1;\n}\n"));
+        assert!(!is_complete("g(1,\n"));
+        assert!(is_complete("puts(\"{(\");"));
+        assert!(is_complete("putchar('}')"));
+    }
+}
@@ -1,15 +1,24 @@
+use crate::core::IoError;
 use std::fs;
 use std::str;
 
+// cut at `//`, unless it is inside a string or char literal
 fn preprocess_code_line(code: String) -> String {
     let bs = code.as_bytes();
-    for i in 1..bs.len() {
-        if bs[i] == b'/' && bs[i - 1] == b'/' {
-            match str::from_utf8(&bs[..i - 1]) {
+    let mut quote = None;
+    let mut i = 0;
+    while i < bs.len() {
+        match (quote, bs[i]) {
+            (Some(_), b'\\') => i += 1,
+            (Some(q), c) if c == q => quote = None,
+            (None, b'"' | b'\'') => quote = Some(bs[i]),
+            (None, b'/') if i > 0 && bs[i - 1] == b'/' => match str::from_utf8(&bs[..i - 1]) {
                 Ok(v) => return v.to_owned(),
                 Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-            };
+            },
+            _ => {}
         }
+        i += 1;
     }
     code
 }
@@ -22,9 +31,20 @@ pub fn preprocess_code(code: String) -> String {
     vec.join("\n")
 }
 
+pub fn read_code(path: &str) -> Result<String, IoError> {
+    match fs::read_to_string(path) {
+        Ok(code) => Ok(preprocess_code(code)),
+        Err(source) => Err(IoError {
+            path: path.to_owned(),
+            source,
+        }),
+    }
+}
+
+// for tests, where a missing fixture is a bug
 #[allow(dead_code)]
 pub fn load_code_from_file(path: &str) -> String {
-    preprocess_code(fs::read_to_string(path).expect("No file"))
+    read_code(path).expect("No file")
 }
 
 #[cfg(test)]
@@ -35,5 +55,8 @@ mod tests {
         let code = "12345//123\n12345";
         let res = preprocess_code(code.to_owned());
         assert_eq!("12345\n12345", res);
+        let code = "char *s = \"a//b\"; // c";
+        let res = preprocess_code(code.to_owned());
+        assert_eq!("char *s = \"a//b\"; ", res);
     }
 }
@@ -0,0 +1,16 @@
+int *g;
+
+int *first(int a[], int n) {
+    return a;
+}
+
+int main() {
+    int x = 0;
+    int *p = &x;
+    int arr[3];
+    p = first(arr, 3);
+    p = 0;
+    if (p == 0) {
+        x = *&x;
+    }
+}
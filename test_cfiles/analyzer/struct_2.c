@@ -0,0 +1,14 @@
+typedef struct {
+    int v;
+} Box;
+struct Box {
+    int w;
+};
+
+int main() {
+    Box a;
+    struct Box b;
+    a.v = 1;
+    b.w = a.v;
+    return b.w;
+}
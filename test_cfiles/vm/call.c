@@ -0,0 +1,34 @@
+int calls;
+
+int gcd(int a, int b) {
+    calls = calls + 1;
+    if (b == 0) {
+        return a;
+    } else {
+        return gcd(b, a % b);
+    }
+}
+
+char upper(char c) {
+    return c - 32;
+}
+
+int count() {
+    return calls;
+}
+
+void reset() {
+    calls = 0;
+}
+
+int sum(int n, ...) {
+    return n;
+}
+
+int div(int a, int b) {
+    return a / b;
+}
+
+int main() {
+    reset();
+}
@@ -0,0 +1,34 @@
+char low(int x) {
+    return x;
+}
+
+int main() {
+    int big = 300;
+    char c = big;
+    c;
+    c = 'A' + 200;
+    c;
+    int n = (char)384;
+    n;
+    n = (int)(char)-129;
+    n;
+    c = low(257);
+    c;
+    int a[2];
+    int *p = a;
+    int addr = (int)p;
+    int *q = (int *)addr;
+    *q = 9;
+    int v = a[0];
+    v;
+    char *s = (char *)p;
+    void *vp = p;
+    int *back = vp;
+    back[1] = 4;
+    v = a[1];
+    v;
+    (void)v;
+    (void)low(1);
+    v = (char)'x';
+    v;
+}
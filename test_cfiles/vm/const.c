@@ -0,0 +1,47 @@
+const int base = 10;
+int derived = base * 2 + 1;
+const char name[] = "const";
+
+struct P {
+    int x;
+    int y;
+};
+
+int sum(const int *p, const int n) {
+    int s = 0;
+    int i = 0;
+    while (i < n) {
+        s = s + p[i];
+        i = i + 1;
+    }
+    return s;
+}
+
+int first(const struct P *p) {
+    return p->x;
+}
+
+int main() {
+    base;
+    derived;
+    int a[3];
+    a[0] = 1;
+    a[1] = 2;
+    a[2] = 3;
+    int s = sum(a, 3);
+    s;
+    const int *cp = &a[1];
+    int v = *cp;
+    v;
+    cp = &a[2];
+    v = *cp;
+    v;
+    struct P q;
+    q.x = 7;
+    v = first(&q);
+    v;
+    const int *b = &base;
+    v = *b;
+    v;
+    puts(name);
+}
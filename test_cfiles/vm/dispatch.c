@@ -0,0 +1,48 @@
+typedef int (*op_t)(int, int);
+
+int add(int a, int b) {
+    return a + b;
+}
+
+int sub(int a, int b) {
+    return a - b;
+}
+
+int mul(int a, int b);
+
+int (*table[3])(int, int) = {add, &sub, mul};
+op_t fallback = &add;
+op_t none;
+char names[4] = {'+', '-', '*'};
+
+int mul(int a, int b) {
+    return a * b;
+}
+
+int run(int i, int a, int b) {
+    if (i < 0 || i > 2) {
+        return fallback(a, b);
+    }
+    return table[i](a, b);
+}
+
+int main() {
+    int x = run(0, 6, 7);
+    x;
+    x = run(1, 6, 7);
+    x;
+    x = run(2, 6, 7);
+    x;
+    x = run(5, 1, 1);
+    x;
+    x = none == 0;
+    x;
+    x = names[2];
+    x;
+    op_t local[] = {mul, sub};
+    x = local[0](3, 4) + local[1](3, 4) + sizeof local;
+    x;
+    int small[4] = {1, 2};
+    x = small[1] + small[3];
+    x;
+}
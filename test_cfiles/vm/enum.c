@@ -0,0 +1,36 @@
+enum Color { RED, GREEN = 5, BLUE };
+typedef int Number;
+typedef struct Point {
+    int x;
+    int y;
+} Point;
+typedef struct {
+    Point a;
+    Point b;
+} Line;
+typedef Point *PointPtr;
+
+int g = BLUE * 2 + 1;
+
+Number add(Number a, Number b) {
+    return a + b;
+}
+
+int main() {
+    enum Color c = GREEN;
+    c;
+    g;
+    Point p;
+    p.x = RED;
+    p.y = BLUE;
+    PointPtr pp = &p;
+    Number n = add(pp->x, pp->y);
+    n;
+    Line l;
+    l.b = p;
+    int y = l.b.y;
+    y;
+    if (c == GREEN) {
+        BLUE;
+    }
+}
@@ -0,0 +1,75 @@
+typedef int (*cmp_t)(int, int);
+
+int asc(int a, int b) {
+    return a - b;
+}
+
+int desc(int a, int b) {
+    return b - a;
+}
+
+void sort(int *a, int n, cmp_t cmp) {
+    int i = 0;
+    while (i < n) {
+        int j = i + 1;
+        while (j < n) {
+            if (cmp(a[i], a[j]) > 0) {
+                int t = a[i];
+                a[i] = a[j];
+                a[j] = t;
+            }
+            j = j + 1;
+        }
+        i = i + 1;
+    }
+}
+
+int add(int a, int b) {
+    return a + b;
+}
+
+int mul(int a, int b) {
+    return a * b;
+}
+
+struct Op {
+    char sym;
+    int (*fn)(int a, int b);
+};
+
+int apply(int (*f)(int, int), int a, int b) {
+    return (*f)(a, b);
+}
+
+int main() {
+    int a[4];
+    a[0] = 3;
+    a[1] = 1;
+    a[2] = 4;
+    a[3] = 2;
+    sort(a, 4, asc);
+    int x = a[0] * 1000 + a[1] * 100 + a[2] * 10 + a[3];
+    x;
+    sort(a, 4, &desc);
+    x = a[0] * 1000 + a[1] * 100 + a[2] * 10 + a[3];
+    x;
+    int (*ops[2])(int, int);
+    ops[0] = add;
+    ops[1] = mul;
+    x = ops[0](6, 7) + ops[1](6, 7);
+    x;
+    struct Op op;
+    op.fn = mul;
+    x = op.fn(3, 5);
+    x;
+    x = apply(add, 2, 3);
+    x;
+    cmp_t f = 0;
+    x = f == 0;
+    x;
+    f = asc;
+    x = f == asc && f != desc;
+    x;
+    x = sizeof(int (*)(int, int)) + sizeof f;
+    x;
+}
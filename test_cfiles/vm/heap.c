@@ -0,0 +1,44 @@
+struct Node {
+    int val;
+    struct Node *next;
+};
+
+struct Node *push(struct Node *head, int v) {
+    struct Node *n = malloc(sizeof(struct Node));
+    n->val = v;
+    n->next = head;
+    return n;
+}
+
+int main() {
+    struct Node *head = 0;
+    int i = 1;
+    while (i <= 4) {
+        head = push(head, i);
+        i = i + 1;
+    }
+    int s = 0;
+    struct Node *p = head;
+    while (p) {
+        s = s * 10 + p->val;
+        p = p->next;
+    }
+    s;
+    while (head) {
+        p = head->next;
+        free(head);
+        head = p;
+    }
+    int *a = calloc(3, sizeof(int));
+    s = a[0] + a[1] + a[2];
+    s;
+    a[2] = 7;
+    a = realloc(a, 5 * sizeof(int));
+    a[4] = 1;
+    s = a[2] + a[4];
+    s;
+    free(0);
+    int *leak = malloc(2);
+    s = malloc(100000) == 0;
+    s;
+}
@@ -0,0 +1,27 @@
+int main() {
+    int a;
+    int b;
+    char c;
+    char word[8];
+    int n = scanf("%d %d", &a, &b);
+    n;
+    int s = a + b;
+    s;
+    n = scanf(" %c %s", &c, word);
+    n;
+    c;
+    puts(word);
+    int x = read_int();
+    x;
+    n = scanf("%d,%d", &a, &b);
+    n;
+    a;
+    int ch = getchar();
+    ch;
+    ch = getchar();
+    ch;
+    n = scanf("%d", &a);
+    n;
+    x = read_int();
+    x;
+}
@@ -0,0 +1,16 @@
+// spawn, hp and tochar are natives of the host
+void spawn(int x, int y);
+
+int main() {
+    int i = 0;
+    while (i < 3) {
+        spawn(i, i * 2);
+        i = i + 1;
+    }
+    int h = hp(2) + hp(3);
+    h;
+    char c = tochar(353);
+    c;
+    h = hp(-1);
+    h;
+}
@@ -0,0 +1,53 @@
+void swap(int *a, int *b) {
+    int t = *a;
+    *a = *b;
+    *b = t;
+}
+
+int sum(int *p, int n) {
+    int s = 0;
+    int *end = p + n;
+    while (p < end) {
+        s = s + *p;
+        p = p + 1;
+    }
+    return s;
+}
+
+int g = 7;
+
+int main() {
+    int x = 1;
+    int y = 2;
+    swap(&x, &y);
+    x;
+    y;
+    int *p = &g;
+    *p = *p + 1;
+    g;
+    int arr[5];
+    int i = 0;
+    while (i < 5) {
+        arr[i] = i * i;
+        i = i + 1;
+    }
+    int *q = arr + 1;
+    int v = *(q + 2);
+    v;
+    int d = arr + 4 - q;
+    d;
+    int s = sum(arr, 5);
+    s;
+    int **pp = &p;
+    **pp = 100;
+    g;
+    int m[2][3];
+    m[1][2] = 12 / 2 * 3;
+    int r = m[1][2];
+    r;
+    int *np = 0;
+    if (!np) {
+        r = 10 - 3 - 2;
+        r;
+    }
+}
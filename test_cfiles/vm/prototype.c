@@ -0,0 +1,13 @@
+int add(int a, int b);
+int g = 5;
+int h[3];
+
+int add(int a, int b) {
+    int c = a + b;
+    return c + g;
+}
+
+int main() {
+    int r = add(3, 4);
+    r;
+}
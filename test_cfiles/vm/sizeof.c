@@ -0,0 +1,40 @@
+struct P {
+    int x;
+    int y;
+};
+
+enum { N = 3 };
+const int M = 2;
+
+struct Q {
+    struct P ps[N];
+    char tag[sizeof(struct P) + 1];
+};
+
+int g = sizeof(struct Q);
+int arr[sizeof(struct P) * M];
+int hits;
+
+int bump() {
+    hits = hits + 1;
+    return hits;
+}
+
+int main() {
+    g;
+    int n = sizeof arr;
+    n;
+    n = sizeof(arr) / sizeof(arr[0]);
+    n;
+    int *p = arr;
+    n = sizeof p + sizeof(char) + sizeof(int *);
+    n;
+    struct Q q;
+    n = sizeof q.tag;
+    n;
+    int local[N * M];
+    n = sizeof local;
+    n;
+    n = sizeof bump();
+    hits;
+}
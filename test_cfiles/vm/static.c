@@ -0,0 +1,39 @@
+extern int total;
+static int calls;
+
+int counter() {
+    static int count = 10;
+    count = count + 1;
+    calls = calls + 1;
+    return count;
+}
+
+int *slot() {
+    static int s[2];
+    return s;
+}
+
+int add(int x) {
+    extern int total;
+    total = total + x;
+    return total;
+}
+
+int total = 100;
+
+int main() {
+    int a = counter();
+    a = counter();
+    a;
+    calls;
+    int *q = slot();
+    q[1] = 7;
+    q = slot();
+    int v = q[1];
+    v;
+    v = add(5);
+    v;
+    total;
+    static char name[] = "static";
+    puts(name);
+}
@@ -0,0 +1,34 @@
+char greeting[] = "hello";
+char *msg = "world!";
+
+int strlen(char *s) {
+    int n = 0;
+    while (s[n] != 0) {
+        n = n + 1;
+    }
+    return n;
+}
+
+int main() {
+    int n = strlen(greeting);
+    n;
+    char c = greeting[0];
+    c;
+    n = strlen(msg);
+    n;
+    puts(greeting);
+    puts("ab\tc // no comment"); // comment
+    puts(msg);
+    char buf[8] = "Hi";
+    int z = buf[7];
+    z;
+    puts(buf);
+    char *p = greeting;
+    while (*p) {
+        putchar(*p);
+        p = p + 1;
+    }
+    putchar('\n');
+    n = strlen("a" "bc");
+    n;
+}
@@ -0,0 +1,68 @@
+struct Point {
+    int x;
+    int y;
+};
+
+struct Rect {
+    struct Point lt;
+    struct Point rb;
+    int tag[2];
+};
+
+struct Node {
+    int v;
+    struct Node *next;
+};
+
+struct Rect gr;
+
+int area(struct Rect r) {
+    return (r.rb.x - r.lt.x) * (r.rb.y - r.lt.y);
+}
+
+struct Point mid(struct Rect *r) {
+    struct Point p;
+    p.x = (r->lt.x + r->rb.x) / 2;
+    p.y = (r->lt.y + r->rb.y) / 2;
+    return p;
+}
+
+int main() {
+    struct Rect r;
+    r.lt.x = 1;
+    r.lt.y = 2;
+    r.rb.x = 5;
+    r.rb.y = 8;
+    int a = area(r);
+    a;
+    struct Point m = mid(&r);
+    int mx = m.x;
+    mx;
+    gr = r;
+    gr.rb = m;
+    int gy = gr.rb.y;
+    gy;
+    int ly = gr.lt.y;
+    ly;
+    struct Node n1;
+    struct Node n2;
+    n1.v = 1;
+    n1.next = &n2;
+    n2.v = 2;
+    n2.next = 0;
+    struct Node *p = &n1;
+    int s = 0;
+    while (p) {
+        s = s + p->v;
+        p = p->next;
+    }
+    s;
+    r.tag[1] = 9;
+    int t = (&r)->tag[1];
+    t;
+    struct Point pts[3];
+    struct Point *q = pts + 2;
+    q->y = 7;
+    int y2 = pts[2].y;
+    y2;
+}
@@ -0,0 +1,33 @@
+int sum(int n, ...) {
+    va_list ap;
+    va_start(ap, n);
+    int s = 0;
+    int i = 0;
+    while (i < n) {
+        s = s + va_arg(ap, int);
+        i = i + 1;
+    }
+    va_end(ap);
+    return s;
+}
+
+char first_char(int n, ...) {
+    va_list ap;
+    va_start(ap, n);
+    char *s = va_arg(ap, char *);
+    return s[0];
+}
+
+int main() {
+    int x = sum(3, 1, 2, 3) + sum(0);
+    x;
+    int (*f)(int, ...) = sum;
+    x = f(2, 10, 20) * 2;
+    x;
+    x = first_char(1, "hello");
+    x;
+    x = printf("%d|%5d|%-4d|%05d|%i|%u\n", 42, 7, 3, -12, -1, -1);
+    x;
+    printf("%x %c%c %s %8s%% %03x\n", 255, 'o', 'k', "str", "right", 10);
+    printf("no args\n");
+}